@@ -0,0 +1,11 @@
+use criterion::{BatchSize, Criterion};
+use gart::interpreter::Interpreter;
+
+/// Times running the script, leaving compilation out.
+pub fn bench_script(c: &mut Criterion, name: &str, source: &str) {
+    c.bench_function(name, |b| b.iter_batched(
+        || Interpreter::builder().build(source.to_owned()).unwrap(),
+        |mut interpreter| interpreter.run().unwrap(),
+        BatchSize::SmallInput,
+    ));
+}
@@ -0,0 +1,38 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+
+mod common;
+use common::bench_script;
+
+/// Calls a function declared before its caller, so every read of it skips the undefined check.
+const DEFINED: &str = r#"
+fn add(a, b):
+    return a + b
+fn run():
+    var i = 0
+    var total = 0
+    while i < 50000:
+        total = add(total, i)
+        i = i + 1
+    return total
+run()"#;
+
+/// The same calls with the function declared after its caller, so every read of it is checked.
+const LATE_BOUND: &str = r#"
+fn run():
+    var i = 0
+    var total = 0
+    while i < 50000:
+        total = add(total, i)
+        i = i + 1
+    return total
+fn add(a, b):
+    return a + b
+run()"#;
+
+fn globals(c: &mut Criterion) {
+    bench_script(c, "defined global calls", DEFINED);
+    bench_script(c, "late bound global calls", LATE_BOUND);
+}
+
+criterion_group!(benches, globals);
+criterion_main!(benches);
@@ -0,0 +1,34 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+
+mod common;
+use common::bench_script;
+
+/// A counting loop on locals, which compiles to superinstructions.
+const FUSED: &str = r#"
+fn count():
+    var i = 0
+    var total = 0
+    while i < 100000:
+        total = total + 3
+        i = i + 1
+    return total
+count()"#;
+
+/// The same loop with its operands swapped, so none of the patterns match and every step is dispatched.
+const UNFUSED: &str = r#"
+fn count():
+    var i = 0
+    var total = 0
+    while 100000 > i:
+        total = 3 + total
+        i = 1 + i
+    return total
+count()"#;
+
+fn locals(c: &mut Criterion) {
+    bench_script(c, "local loop fused", FUSED);
+    bench_script(c, "local loop unfused", UNFUSED);
+}
+
+criterion_group!(benches, locals);
+criterion_main!(benches);
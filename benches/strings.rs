@@ -0,0 +1,40 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+
+mod common;
+use common::bench_script;
+
+// compare against uninterned strings by saving a baseline with the no-interning feature and benching against it without
+
+/// Compares literals that are equal but written in different places, which interning turns into pointer compares.
+const LITERAL_EQUALITY: &str = r#"
+fn state(i):
+    if i < 500:
+        return "the player has not moved yet and the turn is still waiting on them to move"
+    return "the player has not moved yet and the turn is still waiting on them to attack"
+var i = 0
+var matches = 0
+while i < 5000:
+    if state(i) == "the player has not moved yet and the turn is still waiting on them to move":
+        matches = matches + 1
+    i = i + 1"#;
+
+/// Walks a string character by character, every character after the first of its kind is shared.
+const CHARACTER_SCAN: &str = r#"
+var text = "the quick brown fox jumps over the lazy dog"
+var spaces = 0
+var lap = 0
+while lap < 50:
+    var i = 0
+    while i < len(text):
+        if text[i] == " ":
+            spaces = spaces + 1
+        i = i + 1
+    lap = lap + 1"#;
+
+fn strings(c: &mut Criterion) {
+    bench_script(c, "literal equality", LITERAL_EQUALITY);
+    bench_script(c, "character scan", CHARACTER_SCAN);
+}
+
+criterion_group!(benches, strings);
+criterion_main!(benches);
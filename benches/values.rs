@@ -0,0 +1,33 @@
+use std::fs;
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use gart::{interpreter::Interpreter, io::CaptureIo};
+
+/// Runs the example scripts, compare the value representations by saving a baseline without the nan-boxing feature
+/// and benching against it with the feature on.
+fn examples(c: &mut Criterion) {
+    for name in ["fibonacci", "primes", "words"] {
+        let source = fs::read_to_string(format!("examples/scripts/{}.gart", name)).unwrap();
+        c.bench_function(name, |b| b.iter_batched(
+            || Interpreter::builder().io(CaptureIo::new()).build(source.clone()).unwrap(),
+            |mut interpreter| interpreter.run().unwrap(),
+            BatchSize::SmallInput,
+        ));
+    }
+
+    // the guessing game never ends, so play it with the same guesses for a fixed number of instructions
+    let source = fs::read_to_string("examples/scripts/guessing_game.gart").unwrap();
+    c.bench_function("guessing_game", |b| b.iter_batched(
+        || {
+            let guesses = (0..10).flat_map(|_| (0..=100).map(|guess| guess.to_string()));
+            let mut interpreter = Interpreter::builder().io(CaptureIo::with_inputs(guesses)).build(source.clone()).unwrap();
+            interpreter.set_seed(0);
+            return interpreter;
+        },
+        |mut interpreter| interpreter.run_with_budget(100_000).unwrap(),
+        BatchSize::SmallInput,
+    ));
+}
+
+criterion_group!(benches, examples);
+criterion_main!(benches);
@@ -0,0 +1,200 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{Attribute, FnArg, GenericArgument, ImplItem, ItemFn, ItemImpl, LitStr, PathArguments, ReturnType, Signature, Type, parse_macro_input};
+
+/// Turns a Rust function into a native. </br>
+/// Generates a `<name>_native()` function returning the NativeFunction, with the arity and argument conversions derived from the signature.
+/// A first argument of `&mut NativeCtx<T>` receives the context, and returning a Result passes errors to the script.
+/// The script name defaults to the function name, and can be changed with `#[native(name = "...")]`.
+#[proc_macro_attribute]
+pub fn native(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut name = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            name = Some(meta.value()?.parse::<LitStr>()?.value());
+            return Ok(());
+        }
+        return Err(meta.error("Unsupported native attribute, expected `name`."));
+    });
+    parse_macro_input!(attr with parser);
+    let function = parse_macro_input!(item as ItemFn);
+
+    let ident = &function.sig.ident;
+    let native_name = name.unwrap_or_else(|| ident.to_string());
+    let registration = match native_registration(&native_name, quote!(#ident), &function.sig) {
+        Ok(registration) => registration,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let vis = &function.vis;
+    let constructor = format_ident!("{}_native", ident);
+    let doc = format!("Creates the `{}` native.", native_name);
+
+    return quote! {
+        #function
+
+        #[doc = #doc]
+        #vis fn #constructor() -> ::gart::NativeFunction {
+            return #registration;
+        }
+    }.into();
+}
+
+/// Turns every associated function in an impl block into a native. </br>
+/// Generates a `natives()` function returning all of them, ready to hand to the interpreter builder.
+/// Functions can be renamed with `#[native(name = "...")]`.
+#[proc_macro_attribute]
+pub fn module(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        let err = syn::Error::new(proc_macro2::Span::call_site(), "module takes no arguments.");
+        return err.to_compile_error().into();
+    }
+    let mut module = parse_macro_input!(item as ItemImpl);
+
+    let mut registrations = vec![];
+    for item in module.items.iter_mut() {
+        let ImplItem::Fn(function) = item else { continue };
+        let name = match take_native_name(&mut function.attrs) {
+            Ok(name) => name.unwrap_or_else(|| function.sig.ident.to_string()),
+            Err(err) => return err.to_compile_error().into(),
+        };
+        let ident = &function.sig.ident;
+        match native_registration(&name, quote!(Self::#ident), &function.sig) {
+            Ok(registration) => registrations.push(registration),
+            Err(err) => return err.to_compile_error().into(),
+        }
+    }
+
+    let (impl_generics, _, where_clause) = module.generics.split_for_impl();
+    let self_ty = &module.self_ty;
+
+    return quote! {
+        #module
+
+        impl #impl_generics #self_ty #where_clause {
+            /// Creates the natives declared in this module.
+            pub fn natives() -> ::std::vec::Vec<::gart::NativeFunction> {
+                return ::std::vec![#(#registrations),*];
+            }
+        }
+    }.into();
+}
+
+/// Removes a `#[native(name = "...")]` attribute, returning the name if there was one.
+fn take_native_name(attrs: &mut Vec<Attribute>) -> syn::Result<Option<String>> {
+    let mut name = None;
+    let mut result = Ok(());
+    attrs.retain(|attr| {
+        if !attr.path().is_ident("native") {
+            return true;
+        }
+        result = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse::<LitStr>()?.value());
+                return Ok(());
+            }
+            return Err(meta.error("Unsupported native attribute, expected `name`."));
+        });
+        return false;
+    });
+    return result.map(|_| name);
+}
+
+/// Builds the expression creating the NativeFunction that converts its arguments and calls the callee.
+fn native_registration(name: &str, callee: TokenStream2, sig: &Signature) -> syn::Result<TokenStream2> {
+    if !sig.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(&sig.generics, "Natives can't be generic."));
+    }
+
+    let mut inputs = sig.inputs.iter().peekable();
+    // None if the native takes no context, Some(None) for an untyped context and Some(Some(T)) for a context with data T.
+    let mut ctx = None;
+    if let Some(FnArg::Typed(first)) = inputs.peek() {
+        ctx = ctx_data_type(&first.ty).map(|data| data.cloned());
+        if ctx.is_some() {
+            inputs.next();
+        }
+    }
+
+    let mut args = vec![];
+    let mut conversions = vec![];
+    for (index, input) in inputs.enumerate() {
+        let FnArg::Typed(input) = input else {
+            return Err(syn::Error::new_spanned(input, "Natives can't take self."));
+        };
+        let arg = format_ident!("arg{}", index);
+        let ty = &input.ty;
+        conversions.push(quote! {
+            let #arg = <#ty as ::gart::FromValue>::from_arg(native_name, vals, #index)?;
+        });
+        args.push(arg);
+    }
+    if args.len() > u8::MAX as usize {
+        return Err(syn::Error::new_spanned(&sig.inputs, "Natives can take at most 255 arguments."));
+    }
+    let arity = args.len() as u8;
+
+    let call = match ctx {
+        Some(_) => quote!(#callee(ctx, #(#args),*)),
+        None => quote!(#callee(#(#args),*)),
+    };
+    let call = match returns_result(&sig.output) {
+        true => quote!(#call?),
+        false => call,
+    };
+    let ctx_param = match ctx {
+        Some(_) => quote!(ctx),
+        None => quote!(_),
+    };
+    let body = quote! {
+        #(#conversions)*
+        return Ok(::gart::IntoValue::into_value(#call));
+    };
+
+    return Ok(match ctx {
+        Some(Some(data)) => quote! {
+            {
+                let native_name = #name;
+                ::gart::NativeFunction::with_data::<#data>(native_name, #arity, move |#ctx_param, vals| { #body })
+            }
+        },
+        _ => quote! {
+            {
+                let native_name = #name;
+                ::gart::NativeFunction {
+                    name: native_name.to_owned(),
+                    arity: #arity,
+                    function: ::std::boxed::Box::new(move |#ctx_param, vals| {
+                        #body
+                    }),
+                }
+            }
+        },
+    });
+}
+
+/// Checks if the type is `&mut NativeCtx<..>`. </br>
+/// Returns the data type if it has one, or None inside if it's the untyped context.
+fn ctx_data_type(ty: &Type) -> Option<Option<&Type>> {
+    let Type::Reference(reference) = ty else { return None };
+    if reference.mutability.is_none() {
+        return None;
+    }
+    let Type::Path(path) = reference.elem.as_ref() else { return None };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "NativeCtx" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(generics) = &segment.arguments else { return Some(None) };
+    let data = generics.args.iter().find_map(|arg| match arg {
+        GenericArgument::Type(data) => Some(data),
+        _ => None,
+    });
+    return Some(data);
+}
+
+fn returns_result(output: &ReturnType) -> bool {
+    let ReturnType::Type(_, ty) = output else { return false };
+    let Type::Path(path) = ty.as_ref() else { return false };
+    return path.path.segments.last().is_some_and(|segment| segment.ident == "Result");
+}
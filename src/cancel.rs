@@ -0,0 +1,27 @@
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+
+/// Shareable flag for stopping a running script from another thread or callback. </br>
+/// The vm checks it on loop back-jumps and calls, and stops with a Cancelled runtime error once it's set.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    flag: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        return self.flag.load(Ordering::Relaxed);
+    }
+
+    /// Clears the flag so the token can be used for another run.
+    pub fn reset(&self) {
+        self.flag.store(false, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,65 @@
+use std::ops::BitOr;
+
+/// Which groups of built-in natives an interpreter installs. </br>
+/// String, math and conversion natives are always available, everything else has to be granted by the host.
+/// Scripts using a native that wasn't granted fail to compile.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Capabilities {
+    bits: u8,
+}
+
+impl Capabilities {
+    /// No capabilities, only the pure built-ins.
+    pub const NONE: Self = Self { bits: 0 };
+    /// print, input and clear.
+    pub const CONSOLE: Self = Self { bits: 1 };
+    /// time, monotonic and sleep.
+    pub const TIME: Self = Self { bits: 1 << 1 };
+    /// The random natives.
+    pub const RANDOM: Self = Self { bits: 1 << 2 };
+    /// read_file, write_file and file_exists.
+    pub const FILESYSTEM: Self = Self { bits: 1 << 3 };
+    pub const ALL: Self = Self { bits: Self::CONSOLE.bits | Self::TIME.bits | Self::RANDOM.bits | Self::FILESYSTEM.bits };
+
+    /// Profile for embedding where scripts can only compute, such as on a server.
+    pub fn pure() -> Self {
+        return Self::NONE;
+    }
+
+    /// Profile for running scripts in a terminal, the default.
+    pub fn console() -> Self {
+        return Self::CONSOLE | Self::TIME | Self::RANDOM;
+    }
+
+    /// Console profile with access to files.
+    pub fn filesystem() -> Self {
+        return Self::console() | Self::FILESYSTEM;
+    }
+
+    pub fn contains(&self, other: Self) -> bool {
+        return self.bits & other.bits == other.bits;
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        return Self { bits: self.bits | rhs.bits };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::capabilities::Capabilities;
+
+    #[test]
+    fn profiles() {
+        assert!(!Capabilities::pure().contains(Capabilities::CONSOLE));
+        assert!(Capabilities::console().contains(Capabilities::CONSOLE | Capabilities::TIME));
+        assert!(!Capabilities::console().contains(Capabilities::FILESYSTEM));
+        assert!(Capabilities::filesystem().contains(Capabilities::console()));
+        assert_eq!(Capabilities::ALL, Capabilities::filesystem());
+        assert!(Capabilities::pure().contains(Capabilities::NONE));
+    }
+}
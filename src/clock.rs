@@ -0,0 +1,105 @@
+use std::{cell::Cell, rc::Rc, thread, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+
+/// Source of time for the time natives. </br>
+/// Hosts can provide their own to make scripts deterministic, such as in tests or replays.
+pub trait Clock {
+    /// Returns the wall clock time in seconds since the unix epoch.
+    fn time(&self) -> f64;
+    /// Returns seconds since an arbitrary fixed point, never goes backwards.
+    fn monotonic(&self) -> f64;
+    /// Waits for the given number of seconds.
+    fn sleep(&self, seconds: f64);
+}
+
+/// Clock backed by the system time, sleeping blocks the thread.
+pub struct SystemClock {
+    start: Instant
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now()
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl Clock for SystemClock {
+    fn time(&self) -> f64 {
+        return SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs_f64();
+    }
+
+    fn monotonic(&self) -> f64 {
+        return self.start.elapsed().as_secs_f64();
+    }
+
+    /// Negative, NaN or too long durations can't be waited for, and return straight away.
+    fn sleep(&self, seconds: f64) {
+        if let Ok(duration) = Duration::try_from_secs_f64(seconds) {
+            thread::sleep(duration);
+        }
+    }
+}
+
+/// Clock that only moves when told to, sleeping advances time instantly. </br>
+/// Clones share the same time, so the host can keep one to advance or inspect it.
+#[derive(Clone)]
+pub struct VirtualClock {
+    start_time: f64,
+    elapsed: Rc<Cell<f64>>
+}
+
+impl VirtualClock {
+    /// Creates a clock where time() starts at start_time and monotonic() starts at 0.
+    pub fn new(start_time: f64) -> Self {
+        Self {
+            start_time,
+            elapsed: Rc::new(Cell::new(0.0))
+        }
+    }
+
+    pub fn advance(&self, seconds: f64) {
+        self.elapsed.set(self.elapsed.get() + seconds);
+    }
+}
+
+impl Clock for VirtualClock {
+    fn time(&self) -> f64 {
+        return self.start_time + self.elapsed.get();
+    }
+
+    fn monotonic(&self) -> f64 {
+        return self.elapsed.get();
+    }
+
+    fn sleep(&self, seconds: f64) {
+        self.advance(seconds);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::{Clock, VirtualClock};
+
+    #[test]
+    fn virtual_clock_sleep_advances_time() {
+        let clock = VirtualClock::new(100.0);
+        let host_handle = clock.clone();
+
+        clock.sleep(2.5);
+        host_handle.advance(0.5);
+
+        assert_eq!(103.0, clock.time());
+        assert_eq!(3.0, clock.monotonic());
+        assert_eq!(3.0, host_handle.monotonic());
+    }
+}
@@ -1,6 +1,6 @@
 use std::{backtrace, collections::HashMap, rc::Rc};
 
-use crate::{chunk::Chunk, interpreter::CompilerError, opcode::OpCode, parse::{ParseFn, ParsePrecedence, ParseRule}, scanner::Scanner, token::{Token, TokenType}, value::{Function, NativeFunction, Value}};
+use crate::{chunk::Chunk, fold::{fold_binary, fold_unary, is_falsey}, interner::Interner, interpreter::CompilerError, native_module::NativeModule, opcode::OpCode, optimizer::optimize, parse::{ParseFn, ParsePrecedence, ParseRule}, scanner::Scanner, token::{Token, TokenType}, value::{Function, NativeFunction, Value}};
 
 
 pub struct Compiler<'a> {
@@ -8,13 +8,27 @@ pub struct Compiler<'a> {
     source: &'a str,
     previous_token: Token,
     current_token: Token,
+    /// Token after current_token, only scanned when something needs to peek ahead.
+    next_token: Option<Token>,
     had_error: bool,
     panic_mode: bool,
     errors: Vec<CompilerError>,
     globals_state: HashMap<String, (u8, bool, Vec<Token>)>,
     funpiler_stack: Vec<Funpiler>,
-    natives: Vec<NativeFunction>,
-
+    /// Values provided by the host (natives, constants, module members) and the global index they're stored at.
+    host_globals: Vec<(u8, Value)>,
+    /// Modules the host exposed, their members only take a global once the script uses them.
+    modules: HashMap<String, NativeModule>,
+    /// Error messages for globals and modules the host left out, used instead of undefined errors.
+    unavailable: HashMap<String, String>,
+    /// Globals defined by imports, which later imports can replace.
+    imported_globals: Vec<u8>,
+    /// Globals declared by the script itself, which nothing else can replace.
+    script_globals: Vec<u8>,
+    /// Globals holding top-level functions, which are defined for any code compiled after their declaration.
+    function_globals: Vec<u8>,
+    /// Shares string literals between every function in the script, and is handed on to the vm.
+    interner: Interner,
 }
 
 struct Funpiler {
@@ -22,7 +36,22 @@ struct Funpiler {
     scope_depth: usize,
     chunk: Chunk,
     arity: u8,
-    name: String
+    name: String,
+    /// Literal loads emitted in the current declaration, which operators can fold when they're their operands.
+    constant_loads: Vec<ConstantLoad>,
+    /// Furthest offset a forward jump has landed on, code before it can't be folded away.
+    jump_landing: usize,
+    /// Offset of the last Call emitted, so a return can tell if its value comes straight from a call.
+    last_call: Option<usize>,
+}
+
+/// Instruction loading a literal, which can be replaced by its value at compile time.
+struct ConstantLoad {
+    start: usize,
+    end: usize,
+    value: Value,
+    /// Whether the load added its value to the constants, so folding can take it back out.
+    new_constant: bool,
 }
 
 impl Funpiler {
@@ -32,9 +61,21 @@ impl Funpiler {
             scope_depth: 0,
             chunk: Chunk::new(),
             arity: 0,
-            name: name.to_owned()
+            name: name.to_owned(),
+            constant_loads: vec![],
+            jump_landing: 0,
+            last_call: None,
         };
     }
+
+    /// Cuts the chunk's bytes back to len, forgetting the last call if it was cut off.
+    fn truncate_bytes(&mut self, len: usize) {
+        self.chunk.bytes.truncate(len);
+        self.chunk.lines.truncate(len);
+        if self.last_call.is_some_and(|offset| offset >= len) {
+            self.last_call = None;
+        }
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -47,7 +88,8 @@ struct Local {
 pub struct CompilerOutput {
     pub script_function: Function,
     pub globals_count: usize,
-    pub natives: Vec<NativeFunction>,
+    pub host_globals: Vec<(u8, Value)>,
+    pub interner: Interner,
 }
 
 impl<'a> Compiler<'a> {
@@ -57,25 +99,63 @@ impl<'a> Compiler<'a> {
             source: source,
             previous_token: Token::new(TokenType::Error, 0, 0, 0),  // I know.
             current_token: Token::new(TokenType::Error, 0, 0, 0),
+            next_token: None,
             had_error: false,
             panic_mode: false,
             errors: vec![],
             globals_state: HashMap::new(),
-            natives: vec![],
+            host_globals: vec![],
+            modules: HashMap::new(),
+            unavailable: HashMap::new(),
+            imported_globals: vec![],
+            script_globals: vec![],
+            function_globals: vec![],
+            interner: Interner::new(),
             funpiler_stack: vec![]
         }
     }
     pub fn add_native(&mut self, native: NativeFunction) {
-        let index = self.insert_global(native.name.to_owned(), true, None, true);
-        if self.natives.len() == index as usize {
-            self.natives.push(native);
-        }
-        else if self.natives.len() > index as usize {
-            self.natives[index as usize] = native;
+        self.add_host_global(native.name.to_owned(), Value::NativeFunc(Rc::new(native)));
+    }
+    /// Adds a global holding a value provided by the host, such as a constant.
+    pub fn add_constant(&mut self, name: &str, value: Value) {
+        self.add_host_global(name.to_owned(), value);
+    }
+    /// Exposes a module to the script, overwriting any module with the same name.
+    pub fn add_module(&mut self, module: NativeModule) {
+        self.modules.insert(module.name.to_owned(), module);
+    }
+    pub fn has_global(&self, name: &str) -> bool {
+        return self.globals_state.contains_key(name);
+    }
+    pub fn has_module(&self, name: &str) -> bool {
+        return self.modules.contains_key(name);
+    }
+    /// Marks a global or module name as left out by the host, scripts using it get the message as a compile error.
+    pub fn add_unavailable(&mut self, name: &str, message: String) {
+        self.unavailable.insert(name.to_owned(), message);
+    }
+    fn add_host_global(&mut self, name: String, value: Value) {
+        let index = self.insert_global(name, true, None, true);
+        self.set_host_global(index, value);
+    }
+    fn set_host_global(&mut self, index: u8, value: Value) {
+        match self.host_globals.iter_mut().find(|(host_index, _)| *host_index == index) {
+            Some(host_global) => host_global.1 = value,
+            None => self.host_globals.push((index, value)),
         }
-        else {
-            self.error_at_current("Failed to add native function. Index error.");
+    }
+    fn is_host_global(&self, index: u8) -> bool {
+        return self.host_globals.iter().any(|(host_index, _)| *host_index == index);
+    }
+    /// Picks the opcode for reading a global. </br>
+    /// Host globals exist before the script starts, and a function declaration runs before anything compiled after it can,
+    /// so both skip the undefined check. Everything else could be read before it's defined.
+    fn get_global_op(&self, index: u8) -> OpCode {
+        if self.is_host_global(index) || self.function_globals.contains(&index) {
+            return OpCode::GetDefinedGlobal;
         }
+        return OpCode::GetGlobal;
     }
     pub fn compile(mut self) -> Result<CompilerOutput, Vec<CompilerError>> {
         self.new_funpiler(&"script");
@@ -87,11 +167,12 @@ impl<'a> Compiler<'a> {
         let script_function = self.end_funpiler();
 
 
-        let globals: Vec<_> = self.globals_state.values().cloned().collect();
-        for (_, declared, tokens) in globals {
+        let globals: Vec<_> = self.globals_state.iter().map(|(name, state)| (name.to_owned(), state.clone())).collect();
+        for (name, (_, declared, tokens)) in globals {
             if !declared {
+                let message = self.unavailable.get(&name).cloned().unwrap_or("Undefined variable.".to_owned());
                 for token in tokens.iter() {
-                    self.error_at(*token, "Undefined variable.");
+                    self.error_at(*token, &message);
                     self.panic_mode = false;
                 }
             }
@@ -100,7 +181,7 @@ impl<'a> Compiler<'a> {
         if self.had_error {
             return Err(self.errors)
         }
-        return Ok(CompilerOutput { script_function, globals_count: self.globals_state.len(), natives: self.natives });
+        return Ok(CompilerOutput { script_function, globals_count: self.globals_state.len(), host_globals: self.host_globals, interner: self.interner });
     }
 }
 
@@ -109,6 +190,8 @@ impl<'a> Compiler<'a> {
 // Statements/Declarations/Expressions
 impl<'a> Compiler<'a> {
     fn declaration(&mut self) {
+        // expressions never span declarations, so earlier literals can't be folded any more
+        self.funpiler().constant_loads.clear();
         if self.match_token(TokenType::Fn) { 
             if self.funpiler().scope_depth > 0 {
                 self.error_at_previous("Functions can only currently be declared in the top level script, not inside other functions.");
@@ -116,6 +199,7 @@ impl<'a> Compiler<'a> {
             self.fn_declaration(); 
         }
         else if self.match_token(TokenType::Var) { self.var_declaration(); }
+        else if self.match_token(TokenType::Import) { self.import_declaration(); }
         else { self.statement(); }
 
         if self.panic_mode { self.synchronise(); }
@@ -129,6 +213,8 @@ impl<'a> Compiler<'a> {
             self.error_at_previous("Cannot declare function inside another function.");
         }
         let global_index = self.global_identifier(self.previous_token, true);
+        // the body can only run once the function is defined, so it can call itself without the check
+        self.function_globals.push(global_index);
 
         let function = self.function(function_name);
 
@@ -138,6 +224,77 @@ impl<'a> Compiler<'a> {
         self.emit_byte(global_index);
     }
 
+    /// Compiles `import module` which defines a global for every member, or `import module.member` for just one. </br>
+    /// Imports can replace host globals and earlier imports with the same name, but not the script's own globals.
+    fn import_declaration(&mut self) {
+        if self.funpiler_stack.len() > 1 || self.funpiler().scope_depth > 0 {
+            self.error_at_previous("Imports are only allowed at the top level of the script.");
+        }
+        self.consume(TokenType::Identifier, "Expect module name after 'import'.");
+        let module_token = self.previous_token;
+
+        if self.match_token(TokenType::Dot) {
+            self.consume(TokenType::Identifier, "Expect member name after '.'.");
+            let member_token = self.previous_token;
+            let member_name = &self.source[member_token.start..(member_token.start + member_token.length)];
+            self.import_member(module_token, member_name, member_token);
+        }
+        else {
+            let module_name = &self.source[module_token.start..(module_token.start + module_token.length)];
+            let member_names: Vec<String> = match self.modules.get(module_name) {
+                Some(module) => module.members().iter().map(|(name, _)| name.to_owned()).collect(),
+                None => {
+                    self.unknown_module(module_token);
+                    vec![]
+                },
+            };
+            for member_name in member_names.iter() {
+                self.import_member(module_token, member_name, module_token);
+            }
+        }
+        self.consume(TokenType::NewLine, "Expect newline after import.");
+    }
+
+    fn import_member(&mut self, module_token: Token, member_name: &str, member_token: Token) {
+        let Some(member_index) = self.module_member_global(module_token, member_name, member_token) else { return };
+
+        let overwrite = match self.globals_state.get(member_name) {
+            Some((index, _, _)) => (self.is_host_global(*index) || self.imported_globals.contains(index)) && self.script_globals.contains(index) == false,
+            None => false,
+        };
+        let global_index = self.insert_global(member_name.to_owned(), true, Some(member_token), overwrite);
+        self.imported_globals.push(global_index);
+        self.emit_bytes(OpCode::GetDefinedGlobal, member_index);
+        self.emit_bytes(OpCode::DefineGlobal, global_index);
+    }
+
+    /// Gets the globals index holding a module member, giving it one the first time it's used. </br>
+    /// Members are stored under their qualified name, which can't clash with script identifiers.
+    fn module_member_global(&mut self, module_token: Token, member_name: &str, member_token: Token) -> Option<u8> {
+        let module_name = &self.source[module_token.start..(module_token.start + module_token.length)];
+        let member = match self.modules.get(module_name) {
+            Some(module) => module.get(member_name).cloned(),
+            None => {
+                self.unknown_module(module_token);
+                return None;
+            },
+        };
+        let Some(member) = member else {
+            self.error_at(member_token, "Module has no member with this name.");
+            return None;
+        };
+
+        let index = self.insert_global(format!("{}.{}", module_name, member_name), true, None, true);
+        self.set_host_global(index, member);
+        return Some(index);
+    }
+
+    fn unknown_module(&mut self, module_token: Token) {
+        let module_name = &self.source[module_token.start..(module_token.start + module_token.length)];
+        let message = self.unavailable.get(module_name).cloned().unwrap_or("Unknown module.".to_owned());
+        self.error_at(module_token, &message);
+    }
+
     fn new_funpiler(&mut self, function_name: &str) {
         self.funpiler_stack.push(Funpiler::new(function_name));
         self.funpiler().locals.push(Local {
@@ -149,7 +306,8 @@ impl<'a> Compiler<'a> {
     fn end_funpiler(&mut self) -> Function {
         self.emit_byte(OpCode::Null);
         self.emit_byte(OpCode::Return);
-        let funpiler = self.funpiler_stack.pop().unwrap();
+        let mut funpiler = self.funpiler_stack.pop().unwrap();
+        optimize(&mut funpiler.chunk);
         let function = Function {
             name: funpiler.name,
             arity: funpiler.arity,
@@ -227,12 +385,43 @@ impl<'a> Compiler<'a> {
 
     fn call(&mut self) {
         let arg_count = self.arguments();
+        self.funpiler().last_call = Some(self.funpiler().chunk.bytes.len());
         self.emit_bytes(OpCode::Call, arg_count);
     }
 
+    /// Compiles '[index]' or '[start:end]', where either slice bound may be left out.
+    fn index(&mut self) {
+        if self.match_token(TokenType::Colon) {
+            self.emit_byte(OpCode::Null);
+            self.slice_end();
+            return;
+        }
+        self.expression();
+        if self.match_token(TokenType::Colon) {
+            self.slice_end();
+            return;
+        }
+        self.consume(TokenType::RightBracket, "Expect ']' after index.");
+        self.emit_byte(OpCode::Index);
+    }
+
+    fn slice_end(&mut self) {
+        if self.check_token(TokenType::RightBracket) {
+            self.emit_byte(OpCode::Null);
+        }
+        else {
+            self.expression();
+        }
+        self.consume(TokenType::RightBracket, "Expect ']' after slice.");
+        self.emit_byte(OpCode::Slice);
+    }
+
     fn var_declaration(&mut self) {
         self.consume(TokenType::Identifier, "Expect variable name.");
-        if self.funpiler().scope_depth == 0 {
+        if self.check_token(TokenType::Comma) {
+            self.var_destructure();
+        }
+        else if self.funpiler().scope_depth == 0 {
             self.var_global();
         }
         else {
@@ -241,28 +430,9 @@ impl<'a> Compiler<'a> {
     }
 
     fn var_local(&mut self) {
-        let new_local = self.previous_token;
-
-        for i in (0..self.funpiler().locals.len()).rev() {
-            let local = self.funpiler().locals[i];
-            if local.depth != -1 && local.depth < self.funpiler().scope_depth as i32 { break; }
-
-            if self.identifiers_equal(local.token, new_local) {
-                self.error_at_current("Already a variable with this name in scope.");
-                break;
-            }
-        }
-
-        if self.funpiler().locals.len() == u8::MAX as usize{
-            self.error_at_current("Local variable count has been exceeded.");
-        }
-        self.funpiler().locals.push(Local { token: new_local, depth: -1 });
+        self.declare_local(self.previous_token);
 
-        if self.match_token(TokenType::Equal) {
-            self.expression();
-        } else {
-            self.emit_byte(OpCode::Null);
-        }
+        self.var_initialiser();
         self.consume(TokenType::NewLine, "Expect newline after expression.");
 
         let funpiler = self.funpiler();
@@ -274,14 +444,127 @@ impl<'a> Compiler<'a> {
     fn var_global(&mut self) {
         let global_index = self.global_identifier(self.previous_token, true);
 
+        self.var_initialiser();
+        self.consume(TokenType::NewLine, "Expect newline after expression.");
+        self.emit_byte(OpCode::DefineGlobal);
+        self.emit_byte(global_index);
+    }
+
+    /// Compiles the optional '= value' of a single variable declaration. </br>
+    /// Multiple comma separated values are packed into a tuple.
+    fn var_initialiser(&mut self) {
         if self.match_token(TokenType::Equal) {
-            self.expression();
+            let value_count = self.expression_list();
+            if value_count > 1 {
+                self.emit_bytes(OpCode::BuildTuple, value_count);
+            }
         } else {
             self.emit_byte(OpCode::Null);
         }
+    }
+
+    /// Compiles 'var a, b = ...' where the first identifier has already been consumed.
+    fn var_destructure(&mut self) {
+        let is_global = self.funpiler().scope_depth == 0;
+        let mut targets: Vec<Token> = vec![self.previous_token];
+        while self.match_token(TokenType::Comma) {
+            if targets.len() == u8::MAX as usize {
+                self.error_at_current("Cannot destructure into more than 255 variables.");
+                break;
+            }
+            self.consume(TokenType::Identifier, "Expect variable name.");
+            targets.push(self.previous_token);
+        }
+
+        let mut global_indexes: Vec<u8> = vec![];
+        for target in targets.iter() {
+            if is_global { global_indexes.push(self.global_identifier(*target, true)); }
+            else { self.declare_local(*target); }
+        }
+
+        if self.match_token(TokenType::Equal) {
+            let value_count = self.expression_list();
+            self.destructure(targets.len() as u8, value_count);
+        }
+        else {
+            for _ in 0..targets.len() {
+                self.emit_byte(OpCode::Null);
+            }
+        }
         self.consume(TokenType::NewLine, "Expect newline after expression.");
-        self.emit_byte(OpCode::DefineGlobal);
-        self.emit_byte(global_index);
+
+        if is_global {
+            // values are on the stack in declaration order, so define from the top down.
+            for global_index in global_indexes.into_iter().rev() {
+                self.emit_bytes(OpCode::DefineGlobal, global_index);
+            }
+        }
+        else {
+            let funpiler = self.funpiler();
+            let depth = funpiler.scope_depth as i32;
+            for local in funpiler.locals.iter_mut().rev().take(targets.len()) {
+                local.depth = depth;
+            }
+        }
+    }
+
+    /// Compiles 'a, b = ...' as a statement.
+    fn destructure_assignment(&mut self) {
+        let mut targets: Vec<(OpCode, u8)> = vec![];
+        loop {
+            self.consume(TokenType::Identifier, "Expect variable name.");
+            let identifier_token = self.previous_token;
+            let target = match self.local_index(identifier_token) {
+                Some(local_index) => (OpCode::SetLocal, local_index),
+                None => (OpCode::SetGlobal, self.global_identifier(identifier_token, false)),
+            };
+            targets.push(target);
+            if !self.match_token(TokenType::Comma) { break; }
+            if targets.len() == u8::MAX as usize {
+                self.error_at_current("Cannot destructure into more than 255 variables.");
+                break;
+            }
+        }
+        self.consume(TokenType::Equal, "Expect '=' after assignment targets.");
+
+        let value_count = self.expression_list();
+        self.destructure(targets.len() as u8, value_count);
+        self.consume(TokenType::NewLine, "Expect newline after expression.");
+
+        for (set_op, index) in targets.into_iter().rev() {
+            self.emit_bytes(set_op, index);
+            self.emit_byte(OpCode::Pop);
+        }
+    }
+
+    /// Leaves exactly target_count values on the stack. </br>
+    /// A single value is unpacked at runtime, otherwise the counts must match at compile time.
+    fn destructure(&mut self, target_count: u8, value_count: u8) {
+        if value_count == 1 {
+            self.emit_bytes(OpCode::Unpack, target_count);
+        }
+        else if value_count != target_count {
+            let message = format!("Cannot destructure {} values into {} variables.", value_count, target_count);
+            self.error_at_previous(&message);
+        }
+    }
+
+    /// Declares a new local in the current scope, uninitialised until its depth is set.
+    fn declare_local(&mut self, new_local: Token) {
+        for i in (0..self.funpiler().locals.len()).rev() {
+            let local = self.funpiler().locals[i];
+            if local.depth != -1 && local.depth < self.funpiler().scope_depth as i32 { break; }
+
+            if self.identifiers_equal(local.token, new_local) {
+                self.error_at_current("Already a variable with this name in scope.");
+                break;
+            }
+        }
+
+        if self.funpiler().locals.len() == u8::MAX as usize{
+            self.error_at_current("Local variable count has been exceeded.");
+        }
+        self.funpiler().locals.push(Local { token: new_local, depth: -1 });
     }
 
     fn identifiers_equal(&self, a: Token, b: Token) -> bool {
@@ -295,7 +578,14 @@ impl<'a> Compiler<'a> {
     /// If identifier does not exist in globals, it will add it and return index. </br>
     fn global_identifier(&mut self, token: Token, is_declaration: bool) -> u8 {
         let identifier_name = &self.source[token.start..(token.start + token.length)];
-        return self.insert_global(identifier_name.to_owned(), is_declaration, token.into(), false);
+        // scripts can declare names the host already provides, such as `max`, replacing them from the declaration on
+        let shadows_host = is_declaration && match self.globals_state.get(identifier_name) {
+            Some((index, _, _)) => self.is_host_global(*index) && self.script_globals.contains(index) == false,
+            None => false,
+        };
+        let index = self.insert_global(identifier_name.to_owned(), is_declaration, token.into(), shadows_host);
+        if is_declaration { self.script_globals.push(index); }
+        return index;
     }
 
     fn insert_global(&mut self, name: String, is_declaration: bool, token: Option<Token>, overwrite: bool) -> u8 {
@@ -325,9 +615,16 @@ impl<'a> Compiler<'a> {
 
     fn variable(&mut self, can_assign: bool) {
         let identifier_token = self.previous_token;
+        if self.match_token(TokenType::Dot) {
+            self.module_member(identifier_token, can_assign);
+            return;
+        }
         let (get_op, set_op, index): (OpCode, OpCode, u8) = match self.local_index(identifier_token) {
             Some(local_index) => (OpCode::GetLocal, OpCode::SetLocal, local_index),
-            None => (OpCode::GetGlobal, OpCode::SetGlobal, self.global_identifier(identifier_token, false)),
+            None => {
+                let global_index = self.global_identifier(identifier_token, false);
+                (self.get_global_op(global_index), OpCode::SetGlobal, global_index)
+            },
         };
 
         if can_assign && self.match_token(TokenType::Equal) {
@@ -341,6 +638,18 @@ impl<'a> Compiler<'a> {
         }
     }
 
+    fn module_member(&mut self, module_token: Token, can_assign: bool) {
+        self.consume(TokenType::Identifier, "Expect member name after '.'.");
+        let member_token = self.previous_token;
+        let member_name = &self.source[member_token.start..(member_token.start + member_token.length)];
+        let index = self.module_member_global(module_token, member_name, member_token).unwrap_or(0);
+
+        if can_assign && self.match_token(TokenType::Equal) {
+            self.error_at_previous("Can't assign to a module member.");
+        }
+        self.emit_bytes(OpCode::GetDefinedGlobal, index);
+    }
+
     // Tries to find local, returns index if it can. </br>
     // Returns none otherwise.
     fn local_index(&mut self, identifier_token: Token) -> Option<u8> {
@@ -366,6 +675,9 @@ impl<'a> Compiler<'a> {
         else if self.match_token(TokenType::While) {
             self.while_statement();
         }
+        else if self.check_token(TokenType::Identifier) && self.peek_token() == TokenType::Comma {
+            self.destructure_assignment();
+        }
         else if self.match_token(TokenType::Indent) {
             self.begin_scope();
             self.block();
@@ -385,17 +697,42 @@ impl<'a> Compiler<'a> {
             self.emit_bytes(OpCode::Null, OpCode::Return);
         }
         else {
-            self.expression();
+            let value_count = self.expression_list();
+            if value_count > 1 {
+                self.emit_bytes(OpCode::BuildTuple, value_count);
+            }
             self.consume(TokenType::NewLine, "Expect newline after return value.");
+            // `return f(x)`, the Return is still needed for short circuits jumping past the call
+            let call_offset = self.funpiler().chunk.bytes.len().saturating_sub(2);
+            let ends_in_call = self.funpiler().chunk.bytes.get(call_offset) == Some(&OpCode::Call.into());
+            if value_count == 1 && ends_in_call && self.funpiler().last_call == Some(call_offset) {
+                self.funpiler().chunk.bytes[call_offset] = OpCode::TailCall.into();
+            }
             self.emit_byte(OpCode::Return);
         }
     }
 
     fn if_statement(&mut self) {
+        let condition_start = self.funpiler().chunk.bytes.len();
         self.expression();
         self.consume(TokenType::Colon, "Expect ':' after condition.");
         self.consume(TokenType::NewLine, "Expect newline after ':'");
 
+        if let Some(condition) = self.constant_condition(condition_start) {
+            // only one branch can ever run, the other is still compiled for its errors
+            let taken = is_falsey(&condition) == false;
+            self.remove_trailing_constants(1);
+            self.discardable_statement(taken == false);
+            if self.match_token(TokenType::Else) {
+                if !self.check_token(TokenType::If) {
+                    self.consume(TokenType::Colon, "Expect ':' after 'else'.");
+                    self.consume(TokenType::NewLine, "Expect newline after ':'");
+                }
+                self.discardable_statement(taken);
+            }
+            return;
+        }
+
         let then_jump = self.emit_jump(OpCode::JumpIfFalse);
         self.emit_byte(OpCode::Pop);
         self.statement();
@@ -420,6 +757,11 @@ impl<'a> Compiler<'a> {
         self.expression();
         self.consume(TokenType::Colon, "Expect ':' after condition.");
         self.consume(TokenType::NewLine, "Expect newline after ':'");
+        if self.constant_condition(jump_landing).is_some_and(|condition| is_falsey(&condition)) {
+            self.remove_trailing_constants(1);
+            self.discardable_statement(true);
+            return;
+        }
         let loop_break_jump = self.emit_jump(OpCode::JumpIfFalse);
         self.emit_byte(OpCode::Pop);
         self.statement();
@@ -459,10 +801,26 @@ impl<'a> Compiler<'a> {
         self.parse_precedence(ParsePrecedence::Assignment);
     }
 
+    /// Compiles comma separated expressions, leaving each value on the stack. </br>
+    /// Returns the number of expressions compiled.
+    fn expression_list(&mut self) -> u8 {
+        let mut count: u8 = 0;
+        loop {
+            if count == u8::MAX {
+                self.error_at_current("Cannot have more than 255 values in a list.");
+                break;
+            }
+            self.expression();
+            count += 1;
+            if !self.match_token(TokenType::Comma) { break; }
+        }
+        return count;
+    }
+
     fn number(&mut self) {
         let lexeme = &self.source[self.previous_token.start..self.previous_token.length + self.previous_token.start];
         if let Ok(number) = lexeme.parse::<f64>() {
-            self.emit_constant(Value::Number(number));
+            self.emit_literal(Value::Number(number));
         }
         else {
             self.error_at_previous("Failed to parse number.");
@@ -471,17 +829,22 @@ impl<'a> Compiler<'a> {
 
     fn string(&mut self) {
         let val = &self.source[(self.previous_token.start + 1)..(self.previous_token.length + self.previous_token.start - 1)];
-        self.emit_constant(Value::String(Rc::new(val.to_owned())));
+        let string = self.interner.intern(val);
+        self.emit_literal(Value::String(string));
     }
 
     fn binary(&mut self) {
         let operator = self.previous_token.token_type;
+        if operator == TokenType::Not {
+            self.consume(TokenType::In, "Expect 'in' after 'not'.");
+        }
         let operator_rule_prec = self.get_rule(operator).precedence;
         match ParsePrecedence::try_from(u8::from(operator_rule_prec) + 1) {
             Ok(new_precedence) => self.parse_precedence(new_precedence),
             Err(msg) => self.error_at_current(msg),
         }
         
+        if self.fold_binary(operator) { return; }
         match operator {
             TokenType::BangEqual =>     self.emit_bytes(OpCode::Equal, OpCode::Not),
             TokenType::EqualEqual =>    self.emit_byte(OpCode::Equal),
@@ -493,6 +856,8 @@ impl<'a> Compiler<'a> {
             TokenType::Minus =>         self.emit_byte(OpCode::Subtract),
             TokenType::Star =>          self.emit_byte(OpCode::Multiply),
             TokenType::Slash =>         self.emit_byte(OpCode::Divide),
+            TokenType::In =>            self.emit_byte(OpCode::Contains),
+            TokenType::Not =>           self.emit_bytes(OpCode::Contains, OpCode::Not),
             _ => self.error_at_current("binary operator mismatch."),
         };
         
@@ -517,15 +882,22 @@ impl<'a> Compiler<'a> {
 
     fn literal(&mut self) {
         match self.previous_token.token_type {
-            TokenType::True => self.emit_byte(OpCode::True),
-            TokenType::False => self.emit_byte(OpCode::False),
-            TokenType::Null => self.emit_byte(OpCode::Null),
+            TokenType::True => self.emit_literal(Value::Bool(true)),
+            TokenType::False => self.emit_literal(Value::Bool(false)),
+            TokenType::Null => self.emit_literal(Value::Null),
             _ => return
         }
     }
 
     fn grouping(&mut self) {
         self.expression();
+        if self.match_token(TokenType::Comma) {
+            let value_count = self.expression_list() as usize + 1;
+            match u8::try_from(value_count) {
+                Ok(value_count) => self.emit_bytes(OpCode::BuildTuple, value_count),
+                Err(_) => self.error_at_current("Cannot have more than 255 values in a tuple."),
+            }
+        }
         self.consume(TokenType::RightParen, "Expect ')' after expression.");
     }
 
@@ -533,6 +905,7 @@ impl<'a> Compiler<'a> {
         let operator = self.previous_token.token_type;
         self.parse_precedence(ParsePrecedence::Unary);
 
+        if self.fold_unary(operator) { return; }
         match operator {
             TokenType::Bang => self.emit_byte(OpCode::Not),
             TokenType::Minus => self.emit_byte(OpCode::Negate),
@@ -567,6 +940,7 @@ impl<'a> Compiler<'a> {
             ParseFn::Binary => self.binary(),
             ParseFn::Grouping => self.grouping(),
             ParseFn::Call => self.call(),
+            ParseFn::Index => self.index(),
             ParseFn::Unary => self.unary(),
             ParseFn::Variable => self.variable(can_assign),
             ParseFn::String => self.string(),
@@ -580,10 +954,13 @@ impl<'a> Compiler<'a> {
         match token_type {
             TokenType::LeftParen =>     ParseRule::new(ParseFn::Grouping, ParseFn::Call, ParsePrecedence::Call),
             TokenType::RightParen =>    ParseRule::new(ParseFn::None, ParseFn::None, ParsePrecedence::None),
+            TokenType::LeftBracket =>   ParseRule::new(ParseFn::None, ParseFn::Index, ParsePrecedence::Call),
+            TokenType::RightBracket =>  ParseRule::new(ParseFn::None, ParseFn::None, ParsePrecedence::None),
             TokenType::Indent =>        ParseRule::new(ParseFn::None, ParseFn::None, ParsePrecedence::None),
             TokenType::Dedent =>        ParseRule::new(ParseFn::None, ParseFn::None, ParsePrecedence::None),
             TokenType::NewLine =>       ParseRule::new(ParseFn::None, ParseFn::None, ParsePrecedence::None),
             TokenType::Comma =>         ParseRule::new(ParseFn::None, ParseFn::None, ParsePrecedence::None),
+            TokenType::Dot =>           ParseRule::new(ParseFn::None, ParseFn::None, ParsePrecedence::None),
             TokenType::Minus =>         ParseRule::new(ParseFn::Unary, ParseFn::Binary, ParsePrecedence::Term),
             TokenType::Plus =>          ParseRule::new(ParseFn::None, ParseFn::Binary, ParsePrecedence::Term),
             TokenType::Colon =>         ParseRule::new(ParseFn::None, ParseFn::None, ParsePrecedence::None),
@@ -606,6 +983,9 @@ impl<'a> Compiler<'a> {
             TokenType::For =>           ParseRule::new(ParseFn::None, ParseFn::None, ParsePrecedence::None),
             TokenType::Fn =>            ParseRule::new(ParseFn::None, ParseFn::None, ParsePrecedence::None),
             TokenType::If =>            ParseRule::new(ParseFn::None, ParseFn::None, ParsePrecedence::None),
+            TokenType::Import =>        ParseRule::new(ParseFn::None, ParseFn::None, ParsePrecedence::None),
+            TokenType::In =>            ParseRule::new(ParseFn::None, ParseFn::Binary, ParsePrecedence::Comparison),
+            TokenType::Not =>           ParseRule::new(ParseFn::None, ParseFn::Binary, ParsePrecedence::Comparison),
             TokenType::Null =>          ParseRule::new(ParseFn::Literal, ParseFn::None, ParsePrecedence::None),
             TokenType::Or =>            ParseRule::new(ParseFn::None, ParseFn::Or, ParsePrecedence::Or),
             TokenType::Return =>        ParseRule::new(ParseFn::None, ParseFn::None, ParsePrecedence::None),
@@ -637,7 +1017,15 @@ impl<'a> Compiler<'a> {
     }
 
     fn make_constant(&mut self, value: Value) -> u8 {
-        let constant_index = self.funpiler().chunk.write_constant(value);
+        // interned strings are shared, so a repeated literal can reuse the slot it already has
+        let existing = match &value {
+            Value::String(string) => self.funpiler().chunk.constants.iter().position(|constant| matches!(constant, Value::String(other) if Rc::ptr_eq(string, other))),
+            _ => None,
+        };
+        let constant_index = match existing {
+            Some(index) => index,
+            None => self.funpiler().chunk.write_constant(value),
+        };
         if let Ok(index_u8) = u8::try_from(constant_index) {
             return index_u8;
         }
@@ -668,6 +1056,84 @@ impl<'a> Compiler<'a> {
         if jump > u16::MAX.into() { self.error_at_current("Too much code to jump over."); }
         self.funpiler().chunk.bytes[offset] = ((jump >> 8) & 0xff) as u8;
         self.funpiler().chunk.bytes[offset + 1] = (jump & 0xff) as u8;
+        self.funpiler().jump_landing = self.funpiler().chunk.bytes.len();
+    }
+
+    /// Emits a literal, remembering it so operators applied to it can be folded.
+    fn emit_literal(&mut self, value: Value) {
+        let start = self.funpiler().chunk.bytes.len();
+        let constants_count = self.funpiler().chunk.constants.len();
+        match &value {
+            Value::Bool(true) => self.emit_byte(OpCode::True),
+            Value::Bool(false) => self.emit_byte(OpCode::False),
+            Value::Null => self.emit_byte(OpCode::Null),
+            _ => self.emit_constant(value.clone()),
+        }
+        let funpiler = self.funpiler();
+        let new_constant = funpiler.chunk.constants.len() > constants_count;
+        funpiler.constant_loads.push(ConstantLoad { start, end: funpiler.chunk.bytes.len(), value, new_constant });
+    }
+
+    /// Values of the last count literal loads, if they are all the code emitted since the first one started. </br>
+    /// Returns None if anything else was emitted in between, or a jump lands among them.
+    fn trailing_constants(&mut self, count: usize) -> Option<(usize, Vec<Value>)> {
+        let funpiler = self.funpiler();
+        let loads = funpiler.constant_loads.get(funpiler.constant_loads.len().checked_sub(count)?..)?;
+        let start = loads.first()?.start;
+        if funpiler.jump_landing > start || loads.last()?.end != funpiler.chunk.bytes.len() { return None; }
+        if loads.windows(2).any(|pair| pair[0].end != pair[1].start) { return None; }
+        return Some((start, loads.iter().map(|load| load.value.clone()).collect()));
+    }
+
+    /// Removes the code of the last count literal loads, and the constants only they used.
+    fn remove_trailing_constants(&mut self, count: usize) {
+        let funpiler = self.funpiler();
+        for _ in 0..count {
+            let load = funpiler.constant_loads.pop().unwrap();
+            funpiler.truncate_bytes(load.start);
+            if load.new_constant {
+                funpiler.chunk.constants.pop();
+            }
+        }
+    }
+
+    /// Replaces the literal operands of a binary operator with its result. </br>
+    /// Returns false if the operands aren't literals or the operation would fail at runtime.
+    fn fold_binary(&mut self, operator: TokenType) -> bool {
+        let Some((_, operands)) = self.trailing_constants(2) else { return false };
+        let Some(value) = fold_binary(operator, &operands[0], &operands[1], &mut self.interner) else { return false };
+        self.remove_trailing_constants(2);
+        self.emit_literal(value);
+        return true;
+    }
+
+    fn fold_unary(&mut self, operator: TokenType) -> bool {
+        let Some((_, operand)) = self.trailing_constants(1) else { return false };
+        let Some(value) = fold_unary(operator, &operand[0]) else { return false };
+        self.remove_trailing_constants(1);
+        self.emit_literal(value);
+        return true;
+    }
+
+    /// Value of a condition starting at start, if it's a single literal.
+    fn constant_condition(&mut self, start: usize) -> Option<Value> {
+        let (constant_start, mut values) = self.trailing_constants(1)?;
+        if constant_start != start { return None; }
+        return values.pop();
+    }
+
+    /// Compiles a statement, throwing its code away if it can never run. </br>
+    /// It's still compiled so errors in it are reported.
+    fn discardable_statement(&mut self, discard: bool) {
+        let bytes_count = self.funpiler().chunk.bytes.len();
+        let constants_count = self.funpiler().chunk.constants.len();
+        self.statement();
+        if discard == false { return; }
+        let funpiler = self.funpiler();
+        funpiler.truncate_bytes(bytes_count);
+        funpiler.chunk.constants.truncate(constants_count);
+        funpiler.constant_loads.clear();
+        funpiler.jump_landing = funpiler.jump_landing.min(bytes_count);
     }
 }
 
@@ -691,6 +1157,7 @@ impl<'a> Compiler<'a> {
                 | TokenType::Var
                 | TokenType::For
                 | TokenType::If
+                | TokenType::Import
                 | TokenType::While
                 | TokenType::Return => {
                     return;
@@ -705,7 +1172,10 @@ impl<'a> Compiler<'a> {
     fn advance(&mut self) {
         self.previous_token = self.current_token;
         loop {
-            self.current_token = self.scanner.scan_token();
+            self.current_token = match self.next_token.take() {
+                Some(token) => token,
+                None => self.scanner.scan_token(),
+            };
             if self.current_token.token_type != TokenType::Error { break; }
             let error_message = self .scanner .previous_error().map(|s| s.clone()).unwrap_or_else(|| "No error message.".to_string()); 
             self.error_at_current(&error_message);
@@ -730,6 +1200,14 @@ impl<'a> Compiler<'a> {
         return self.current_token.token_type == token_type;
     }
 
+    /// Returns the type of the token after the current one without consuming anything.
+    fn peek_token(&mut self) -> TokenType {
+        if self.next_token.is_none() {
+            self.next_token = Some(self.scanner.scan_token());
+        }
+        return self.next_token.unwrap().token_type;
+    }
+
     fn error_at_current(&mut self, message: &str) {
         self.error_at(self.current_token, message);
     }
@@ -762,7 +1240,9 @@ impl<'a> Compiler<'a> {
 
 #[cfg(test)]
 mod test {
-    use crate::{NativeFunction, chunk::Chunk, compiler::{Compiler, CompilerError}, opcode::OpCode, value::Value};
+    use std::rc::Rc;
+
+    use crate::{NativeFunction, chunk::Chunk, compiler::{Compiler, CompilerError}, interpreter::RuntimeError, native_module::NativeModule, opcode::OpCode, value::Value, vm::NativeCtx};
 
     #[test]
     fn arithmetic() {
@@ -773,26 +1253,12 @@ mod test {
             bytes: vec![
                 OpCode::Constant.into(),
                 0,
-                OpCode::Constant.into(),
-                1,
-                OpCode::Constant.into(),
-                2,
-                OpCode::Constant.into(),
-                3,
-                OpCode::Subtract.into(),
-                OpCode::Multiply.into(),
-                OpCode::Add.into(),
                 OpCode::Pop.into(),
                 OpCode::Null.into(),
                 OpCode::Return.into()
             ],
-            lines: vec![1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
-            constants: vec![
-                Value::Number(1.0), 
-                Value::Number(2.0), 
-                Value::Number(5.0), 
-                Value::Number(3.0)
-            ]
+            lines: vec![1, 1, 1, 1, 1],
+            constants: vec![Value::Number(5.0)]
         };
         
         let output = compiler.compile();
@@ -824,13 +1290,12 @@ mod test {
             bytes: vec![
                 OpCode::Constant.into(),
                 0,
-                OpCode::Negate.into(),
                 OpCode::Pop.into(),
                 OpCode::Null.into(),
                 OpCode::Return.into()
             ],
-            lines: vec![1, 1, 1, 1, 1, 1],
-            constants: vec![Value::Number(10.4)],
+            lines: vec![1, 1, 1, 1, 1],
+            constants: vec![Value::Number(-10.4)],
         };
 
         let output = compiler.compile();
@@ -1067,17 +1532,12 @@ if true:
         
         let expected_chunk = Chunk {
             bytes: vec![
-                OpCode::True.into(),
-                OpCode::JumpIfFalse.into(), 0, 7,
-                OpCode::Pop.into(),
                 OpCode::Constant.into(), 0,
                 OpCode::Pop.into(),
-                OpCode::Jump.into(), 0, 1,
-                OpCode::Pop.into(),
                 OpCode::Null.into(),
                 OpCode::Return.into()
             ],
-            lines: vec![2, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 3, 3, 3],
+            lines: vec![3, 3, 3, 3, 3],
             constants: vec![Value::Number(2.0)],
         };
         let expected_global_count = 0;
@@ -1097,9 +1557,9 @@ if true:
             name: "print".to_owned(),
             arity: 1,
             function: {
-                fn print(vals: &[Value]) -> Value {
+                fn print(_: &mut NativeCtx, vals: &[Value]) -> Result<Value, RuntimeError> {
                     println!("{}", vals[0]);
-                    return Value::Null;
+                    return Ok(Value::Null);
                 }
                 Box::new(print)
             },
@@ -1109,21 +1569,16 @@ if true:
         
         let expected_chunk = Chunk {
             bytes: vec![
-                OpCode::True.into(),
-                OpCode::JumpIfFalse.into(), 0, 14,
-                OpCode::Pop.into(),
                 OpCode::Constant.into(), 0,
-                OpCode::GetGlobal.into(), 0,
+                OpCode::GetDefinedGlobal.into(), 0,
                 OpCode::GetLocal.into(), 1,
                 OpCode::Call.into(), 1,
                 OpCode::Pop.into(),
                 OpCode::Pop.into(),
-                OpCode::Jump.into(), 0, 1,
-                OpCode::Pop.into(),
                 OpCode::Null.into(),
                 OpCode::Return.into() 
             ],
-            lines: vec![2, 2, 2, 2, 2, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4],
+            lines: vec![3, 3, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4],
             constants: vec![Value::Number(2.0)],
         };
         let expected_global_count = 1;
@@ -1132,5 +1587,327 @@ if true:
         assert_eq!(expected_chunk, output.script_function.chunk);
         assert_eq!(expected_global_count, output.globals_count);    
     }
+    #[test]
+    fn global_destructure() {
+        let source = r#"var a, b = 1, 2"#;
+        let compiler = Compiler::new(&source);
+
+        let expected_chunk = Chunk {
+            bytes: vec![
+                OpCode::Constant.into(), 0,
+                OpCode::Constant.into(), 1,
+                OpCode::DefineGlobal.into(), 1,
+                OpCode::DefineGlobal.into(), 0,
+                OpCode::Null.into(),
+                OpCode::Return.into()
+            ],
+            lines: vec![1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+            constants: vec![Value::Number(1.0), Value::Number(2.0)],
+        };
+
+        let output = compiler.compile().expect("Failed to compile");
+        assert_eq!(expected_chunk, output.script_function.chunk);
+        assert_eq!(2, output.globals_count);
+    }
+
+    #[test]
+    fn destructure_assignment_unpacks_single_value() {
+        let source = r#"
+var a = 1
+var b = 2
+a, b = (b, a)"#;
+        let compiler = Compiler::new(&source);
+
+        let expected_chunk = Chunk {
+            bytes: vec![
+                OpCode::Constant.into(), 0,
+                OpCode::DefineGlobal.into(), 0,
+                OpCode::Constant.into(), 1,
+                OpCode::DefineGlobal.into(), 1,
+                OpCode::GetGlobal.into(), 1,
+                OpCode::GetGlobal.into(), 0,
+                OpCode::BuildTuple.into(), 2,
+                OpCode::Unpack.into(), 2,
+                OpCode::SetGlobal.into(), 1,
+                OpCode::Pop.into(),
+                OpCode::SetGlobal.into(), 0,
+                OpCode::Pop.into(),
+                OpCode::Null.into(),
+                OpCode::Return.into()
+            ],
+            lines: vec![2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4],
+            constants: vec![Value::Number(1.0), Value::Number(2.0)],
+        };
 
-}
\ No newline at end of file
+        let output = compiler.compile().expect("Failed to compile");
+        assert_eq!(expected_chunk, output.script_function.chunk);
+    }
+
+    #[test]
+    fn error_destructure_count_mismatch() {
+        let source = r#"var a, b = 1, 2, 3"#;
+        let compiler = Compiler::new(&source);
+
+        let expected_err_positions: Vec<(usize, usize, usize)> = vec![(1, 17, 1)];
+
+        let output = compiler.compile().unwrap_err();
+        assert_eq!(expected_err_positions.len(), output.len());
+        for (line, start, len) in expected_err_positions.iter() {
+            assert_eq!(*line, output[0].line);
+            assert_eq!(*start, output[0].start);
+            assert_eq!(*len, output[0].len);
+        }
+    }
+    #[test]
+    fn module_member_global() {
+        let source = r#"
+math.PI
+math.PI
+import math.PI"#;
+        let mut compiler = Compiler::new(&source);
+        let mut module = NativeModule::new("math");
+        module.add_constant("PI", Value::Number(3.0));
+        compiler.add_module(module);
+
+        let expected_chunk = Chunk {
+            bytes: vec![
+                OpCode::GetDefinedGlobal.into(),
+                0,
+                OpCode::Pop.into(),
+                OpCode::GetDefinedGlobal.into(),
+                0,
+                OpCode::Pop.into(),
+                OpCode::GetDefinedGlobal.into(),
+                0,
+                OpCode::DefineGlobal.into(),
+                1,
+                OpCode::Null.into(),
+                OpCode::Return.into(),
+            ],
+            constants: vec![],
+            lines: vec![2, 2, 2, 3, 3, 3, 4, 4, 4, 4, 4, 4],
+        };
+
+        let output = compiler.compile().unwrap();
+        assert_eq!(expected_chunk, output.script_function.chunk);
+        assert_eq!(vec![(0, Value::Number(3.0))], output.host_globals);
+    }
+
+    #[test]
+    fn error_modules() {
+        let sources = [
+            ("unknown.x", "Unknown module."),
+            ("math.tau", "Module has no member with this name."),
+            ("math.PI = 3", "Can't assign to a module member."),
+            ("import nothing", "Unknown module."),
+            ("fn f():\n    import math\n", "Imports are only allowed at the top level of the script."),
+            ("var PI = 1\nimport math", "Aready a global variable with this name."),
+        ];
+        for (source, message) in sources {
+            let mut compiler = Compiler::new(&source);
+            let mut module = NativeModule::new("math");
+            module.add_constant("PI", Value::Number(3.0));
+            compiler.add_module(module);
+
+            let output = compiler.compile().unwrap_err();
+            assert_eq!(message, output[0].message, "Source: {}", source);
+        }
+    }
+
+    #[test]
+    fn not_in() {
+        let source = r#"var s = "abc"
+"a" not in s"#;
+        let compiler = Compiler::new(&source);
+
+        let expected_chunk = Chunk {
+            bytes: vec![
+                OpCode::Constant.into(), 0,
+                OpCode::DefineGlobal.into(), 0,
+                OpCode::Constant.into(), 1,
+                OpCode::GetGlobal.into(), 0,
+                OpCode::Contains.into(),
+                OpCode::Not.into(),
+                OpCode::Pop.into(),
+                OpCode::Null.into(),
+                OpCode::Return.into()
+            ],
+            lines: vec![1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2],
+            constants: vec![Value::String(Rc::new("abc".to_owned())), Value::String(Rc::new("a".to_owned()))],
+        };
+
+        let output = compiler.compile().expect("Failed to compile");
+        assert_eq!(expected_chunk, output.script_function.chunk);
+
+        // not only starts not in, ! is the negation
+        let errors = Compiler::new("not true").compile().unwrap_err();
+        assert_eq!("Expected expression.", errors[0].message);
+        let errors = Compiler::new("\"a\" not \"abc\"").compile().unwrap_err();
+        assert_eq!("Expect 'in' after 'not'.", errors[0].message);
+    }
+
+    #[test]
+    fn constant_folding() {
+        let source = r#"
+var greeting = "hello" + " " + "world"
+var check = !(2 >= 3) and "ell" in "hello"
+var a = 1
+var b = (a and 2) + 3
+var c = a + 2 * 3"#;
+        let output = Compiler::new(&source).compile().expect("Failed to compile");
+
+        let expected_chunk = Chunk {
+            bytes: vec![
+                OpCode::Constant.into(), 0,
+                OpCode::DefineGlobal.into(), 0,
+                // and isn't folded, its operands are
+                OpCode::True.into(),
+                OpCode::JumpIfFalse.into(), 0, 2,
+                OpCode::Pop.into(),
+                OpCode::True.into(),
+                OpCode::DefineGlobal.into(), 1,
+                OpCode::Constant.into(), 1,
+                OpCode::DefineGlobal.into(), 2,
+                // the short circuit lands between 2 and 3, so they stay apart
+                OpCode::GetGlobal.into(), 2,
+                OpCode::JumpIfFalse.into(), 0, 3,
+                OpCode::Pop.into(),
+                OpCode::Constant.into(), 2,
+                OpCode::Constant.into(), 3,
+                OpCode::Add.into(),
+                OpCode::DefineGlobal.into(), 3,
+                OpCode::GetGlobal.into(), 2,
+                OpCode::Constant.into(), 4,
+                OpCode::Add.into(),
+                OpCode::DefineGlobal.into(), 4,
+                OpCode::Null.into(),
+                OpCode::Return.into(),
+            ],
+            lines: vec![2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 6, 6, 6, 6, 6, 6, 6, 6, 6],
+            constants: vec![
+                Value::String(Rc::new("hello world".to_owned())),
+                Value::Number(1.0),
+                Value::Number(2.0),
+                Value::Number(3.0),
+                Value::Number(6.0),
+            ],
+        };
+        assert_eq!(expected_chunk, output.script_function.chunk);
+    }
+
+    #[test]
+    fn dead_branches() {
+        let source = r#"
+var x = 0
+if false:
+    x = 1
+else:
+    x = 2
+if 1 > 2:
+    x = 3
+while false:
+    x = 4
+if null:
+    fn_that_is_not_defined()"#;
+        let compiler = Compiler::new(&source);
+
+        let expected_chunk = Chunk {
+            bytes: vec![
+                OpCode::Constant.into(), 0,
+                OpCode::DefineGlobal.into(), 0,
+                OpCode::Constant.into(), 1,
+                OpCode::SetGlobal.into(), 0,
+                OpCode::Pop.into(),
+                OpCode::Null.into(),
+                OpCode::Return.into(),
+            ],
+            lines: vec![2, 2, 2, 2, 6, 6, 6, 6, 6, 11, 11],
+            constants: vec![Value::Number(0.0), Value::Number(2.0)],
+        };
+        // discarded branches are still compiled, so their errors are reported
+        let errors = compiler.compile().unwrap_err();
+        assert_eq!(1, errors.len());
+
+        let source = &source[..source.find("if null").unwrap()];
+        let output = Compiler::new(source).compile().expect("Failed to compile");
+        assert_eq!(expected_chunk, output.script_function.chunk);
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-interning"))]
+    fn interned_strings() {
+        let source = r#"
+fn greet():
+    return "hello"
+var a = "hello"
+var b = "hello" + "world""#;
+        let output = Compiler::new(&source).compile().expect("Failed to compile");
+
+        let constants = &output.script_function.chunk.constants;
+        let Some(Value::Func(greet)) = constants.first() else { panic!("Expected the greet function first.") };
+        let Value::String(in_greet) = &greet.chunk.constants[0] else { panic!("Expected a string constant.") };
+        let strings: Vec<&Rc<String>> = constants.iter().filter_map(|constant| match constant {
+            Value::String(string) => Some(string),
+            _ => None,
+        }).collect();
+        // the second "hello" reuses the first one's slot
+        assert_eq!(2, strings.len());
+        assert!(Rc::ptr_eq(in_greet, strings[0]));
+        assert!(Rc::ptr_eq(strings[0], &output.interner.get("hello").unwrap()));
+    }
+
+    #[test]
+    fn tail_calls() {
+        let source = r#"
+fn a(n):
+    return a(n)
+fn b(n):
+    return 1 + b(n)
+fn c(n):
+    return b(n), c(n)
+fn d(n):
+    return b(c(n))"#;
+        let output = Compiler::new(source).compile().expect("Failed to compile");
+        let constants = &output.script_function.chunk.constants;
+        let has_tail_call = |index: usize| {
+            let Value::Func(function) = &constants[index] else { panic!("Expected a function constant") };
+            return function.chunk.bytes.contains(&OpCode::TailCall.into());
+        };
+        assert!(has_tail_call(0));
+        assert!(has_tail_call(1) == false);
+        assert!(has_tail_call(2) == false);
+        assert!(has_tail_call(3));
+    }
+
+    #[test]
+    fn defined_globals() {
+        let source = r#"
+fn early():
+    return late()
+fn late():
+    return late
+var x = 1
+fn after():
+    return early, x"#;
+        let output = Compiler::new(source).compile().expect("Failed to compile");
+        let constants = &output.script_function.chunk.constants;
+        let first_get = |index: usize| {
+            let Value::Func(function) = &constants[index] else { panic!("Expected a function constant") };
+            return OpCode::try_from(function.chunk.bytes[0]).unwrap();
+        };
+        // late is read before its declaration is compiled, so it could still be undefined
+        assert_eq!(OpCode::GetGlobal, first_get(0));
+        assert_eq!(OpCode::GetDefinedGlobal, first_get(1));
+
+        let Value::Func(after) = &constants[3] else { panic!("Expected a function constant") };
+        let expected_bytes: Vec<u8> = vec![
+            OpCode::GetDefinedGlobal.into(), 0,
+            OpCode::GetGlobal.into(), 2,
+            OpCode::BuildTuple.into(), 2,
+            OpCode::Return.into(),
+            OpCode::Null.into(),
+            OpCode::Return.into(),
+        ];
+        assert_eq!(expected_bytes, after.chunk.bytes);
+    }
+}
@@ -0,0 +1,252 @@
+use std::rc::Rc;
+
+use crate::{interpreter::RuntimeError, value::{HostObject, NativeFunction, Value}};
+
+/// Converts a script value into a Rust type, used for the arguments of typed natives.
+pub trait FromValue: Sized {
+    /// Name of the expected type, used in argument errors.
+    fn type_name() -> String;
+    /// Returns None if the value is not of this type.
+    fn from_value(value: &Value) -> Option<Self>;
+
+    /// Converts the native argument at index, or an error naming the native and the expected type.
+    fn from_arg(native_name: &str, vals: &[Value], index: usize) -> Result<Self, RuntimeError> {
+        return match Self::from_value(&vals[index]) {
+            Some(val) => Ok(val),
+            None => Err(RuntimeError::new(format!("{} expects a {} for argument {}.", native_name, Self::type_name(), index + 1))),
+        };
+    }
+}
+
+/// Converts a Rust type into a script value, used for the return values of typed natives.
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+impl FromValue for Value {
+    fn type_name() -> String {
+        return "any value".to_owned();
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        return Some(value.clone());
+    }
+}
+
+impl FromValue for f64 {
+    fn type_name() -> String {
+        return "number".to_owned();
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        return match value {
+            Value::Number(num) => Some(*num),
+            _ => None,
+        };
+    }
+}
+
+impl FromValue for bool {
+    fn type_name() -> String {
+        return "bool".to_owned();
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        return match value {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        };
+    }
+}
+
+impl FromValue for String {
+    fn type_name() -> String {
+        return "string".to_owned();
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        return match value {
+            Value::String(string) => Some(string.to_string()),
+            _ => None,
+        };
+    }
+}
+
+/// Null becomes None.
+impl<T: FromValue> FromValue for Option<T> {
+    fn type_name() -> String {
+        return format!("{} or null", T::type_name());
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        return match value {
+            Value::Null => Some(None),
+            _ => T::from_value(value).map(Some),
+        };
+    }
+}
+
+/// Converts a tuple where every value is a T.
+impl<T: FromValue> FromValue for Vec<T> {
+    fn type_name() -> String {
+        return format!("tuple of {}", T::type_name());
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        return match value {
+            Value::Tuple(values) => values.iter().map(T::from_value).collect(),
+            _ => None,
+        };
+    }
+}
+
+impl<T: HostObject> FromValue for Rc<T> {
+    fn type_name() -> String {
+        return std::any::type_name::<T>().to_owned();
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        return value.host_rc::<T>();
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        return self;
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        return Value::Null;
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        return Value::Number(self);
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        return Value::Bool(self);
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        return Value::String(Rc::new(self));
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        return Value::String(Rc::new(self.to_owned()));
+    }
+}
+
+/// None becomes null.
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        return match self {
+            Some(value) => value.into_value(),
+            None => Value::Null,
+        };
+    }
+}
+
+/// Becomes a tuple.
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        return Value::Tuple(Rc::new(self.into_iter().map(IntoValue::into_value).collect()));
+    }
+}
+
+impl<T: HostObject> IntoValue for Rc<T> {
+    fn into_value(self) -> Value {
+        return Value::Host(self);
+    }
+}
+
+/// Rust closures that can be turned into natives, with the arity and argument conversions derived from their signature. </br>
+/// Args is the tuple of argument types, only there to tell the implementations apart.
+pub trait IntoNative<Args> {
+    fn into_native(self, name: &str) -> NativeFunction;
+}
+
+macro_rules! impl_into_native {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg),*> IntoNative<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: IntoValue,
+            $($arg: FromValue),*
+        {
+            #[allow(non_snake_case, unused_mut, unused_variables, unused_assignments)]
+            fn into_native(self, name: &str) -> NativeFunction {
+                let native_name = name.to_owned();
+                let arity = <[&str]>::len(&[$(stringify!($arg)),*]) as u8;
+                return NativeFunction {
+                    name: name.to_owned(),
+                    arity,
+                    function: Box::new(move |_, vals| {
+                        let mut index = 0;
+                        $(
+                            let $arg = $arg::from_arg(&native_name, vals, index)?;
+                            index += 1;
+                        )*
+                        return Ok((self)($($arg),*).into_value());
+                    }),
+                };
+            }
+        }
+    };
+}
+
+impl_into_native!();
+impl_into_native!(A);
+impl_into_native!(A, B);
+impl_into_native!(A, B, C);
+impl_into_native!(A, B, C, D);
+impl_into_native!(A, B, C, D, E);
+impl_into_native!(A, B, C, D, E, G);
+
+impl NativeFunction {
+    /// Creates a native from a Rust closure, such as `|a: f64, b: f64| a + b`. </br>
+    /// Arguments of the wrong type are a runtime error naming the native.
+    pub fn from_fn<Args>(name: &str, function: impl IntoNative<Args>) -> Self {
+        return function.into_native(name);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use crate::{convert::{FromValue, IntoValue}, value::Value};
+
+    #[test]
+    fn from_value() {
+        assert_eq!(Some(1.5), f64::from_value(&Value::Number(1.5)));
+        assert_eq!(None, f64::from_value(&Value::Null));
+        assert_eq!(Some(true), bool::from_value(&Value::Bool(true)));
+        assert_eq!(Some("hi".to_owned()), String::from_value(&"hi".into_value()));
+        assert_eq!(Some(None), Option::<f64>::from_value(&Value::Null));
+        assert_eq!(Some(Some(2.0)), Option::<f64>::from_value(&Value::Number(2.0)));
+        assert_eq!(None, Option::<f64>::from_value(&Value::Bool(false)));
+
+        let tuple = Value::Tuple(Rc::new(vec![Value::Number(1.0), Value::Number(2.0)]));
+        assert_eq!(Some(vec![1.0, 2.0]), Vec::<f64>::from_value(&tuple));
+        assert_eq!(None, Vec::<bool>::from_value(&tuple));
+        assert_eq!("tuple of number or null", Vec::<Option<f64>>::type_name());
+    }
+
+    #[test]
+    fn into_value() {
+        assert_eq!(Value::Null, ().into_value());
+        assert_eq!(Value::Null, None::<f64>.into_value());
+        assert_eq!(Value::Number(3.0), Some(3.0).into_value());
+        assert_eq!(Value::Tuple(Rc::new(vec![Value::Bool(true), Value::Null])), vec![Some(true), None].into_value());
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{interner::Interner, token::TokenType, value::Value};
+
+/// Works out a binary operator on two constants at compile time. </br>
+/// Returns None if the operation would be a runtime error, so it's left for the vm to report.
+pub(crate) fn fold_binary(operator: TokenType, a: &Value, b: &Value, interner: &mut Interner) -> Option<Value> {
+    let value = match (operator, a, b) {
+        (TokenType::EqualEqual, _, _) => Value::Bool(a == b),
+        (TokenType::BangEqual, _, _) => Value::Bool(a != b),
+        (TokenType::Plus, Value::Number(a), Value::Number(b)) => Value::Number(a + b),
+        (TokenType::Plus, Value::String(a), Value::String(b)) => Value::String(interner.intern(&(a.as_str().to_owned() + b.as_str()))),
+        (TokenType::Minus, Value::Number(a), Value::Number(b)) => Value::Number(a - b),
+        (TokenType::Star, Value::Number(a), Value::Number(b)) => Value::Number(a * b),
+        (TokenType::Slash, Value::Number(a), Value::Number(b)) => Value::Number(a / b),
+        (TokenType::Greater, Value::Number(a), Value::Number(b)) => Value::Bool(a > b),
+        (TokenType::Less, Value::Number(a), Value::Number(b)) => Value::Bool(a < b),
+        // same as the vm, not-less rather than greater-or-equal
+        (TokenType::GreaterEqual, Value::Number(a), Value::Number(b)) => Value::Bool((a < b) == false),
+        (TokenType::LessEqual, Value::Number(a), Value::Number(b)) => Value::Bool((a > b) == false),
+        (TokenType::In, Value::String(sub_string), Value::String(string)) => Value::Bool(string.contains(sub_string.as_str())),
+        (TokenType::Not, Value::String(sub_string), Value::String(string)) => Value::Bool(string.contains(sub_string.as_str()) == false),
+        _ => return None,
+    };
+    return Some(value);
+}
+
+pub(crate) fn fold_unary(operator: TokenType, value: &Value) -> Option<Value> {
+    return match (operator, value) {
+        (TokenType::Minus, Value::Number(num)) => Some(Value::Number(-num)),
+        (TokenType::Bang, _) => Some(Value::Bool(is_falsey(value))),
+        _ => None,
+    };
+}
+
+pub(crate) fn is_falsey(value: &Value) -> bool {
+    return *value == Value::Null || *value == Value::Bool(false);
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use crate::{fold::{fold_binary, fold_unary}, interner::Interner, token::TokenType, value::Value};
+
+    #[test]
+    fn fold() {
+        let mut interner = Interner::new();
+        let string = |s: &str| Value::String(Rc::new(s.to_owned()));
+        assert_eq!(Some(Value::Number(5.0)), fold_binary(TokenType::Plus, &Value::Number(2.0), &Value::Number(3.0), &mut interner));
+        assert_eq!(Some(string("ab")), fold_binary(TokenType::Plus, &string("a"), &string("b"), &mut interner));
+        assert_eq!(Some(Value::Bool(true)), fold_binary(TokenType::GreaterEqual, &Value::Number(f64::NAN), &Value::Number(1.0), &mut interner));
+        assert_eq!(Some(Value::Bool(false)), fold_binary(TokenType::EqualEqual, &Value::Null, &Value::Bool(false), &mut interner));
+        assert_eq!(None, fold_binary(TokenType::Plus, &Value::Number(1.0), &string("a"), &mut interner));
+        assert_eq!(None, fold_binary(TokenType::Less, &string("a"), &string("b"), &mut interner));
+
+        assert_eq!(Some(Value::Bool(true)), fold_unary(TokenType::Bang, &Value::Null));
+        assert_eq!(None, fold_unary(TokenType::Minus, &Value::Bool(true)));
+    }
+}
@@ -0,0 +1,364 @@
+use std::{collections::HashMap, mem::{size_of, size_of_val}, rc::{Rc, Weak}};
+
+use crate::value::{HostObject, Value};
+
+/// Keeps track of the strings, tuples and host objects the vm creates. </br>
+/// Counts their bytes so hosts can cap script memory, and collects the ones only kept alive by reference cycles.
+/// Allocations are tracked through weak references, so values freed by Rc stop counting once the heap sweeps them.
+pub(crate) struct Heap {
+    allocations: Vec<Allocation>,
+    /// Index of the allocation at each address, so values handed back by natives aren't counted twice.
+    addresses: HashMap<usize, usize>,
+    /// Bytes held by tracked allocations, including ones freed since the last sweep.
+    bytes: usize,
+    pub limit: Option<usize>,
+    /// Sweep and collect once this many allocations are tracked, so the list doesn't grow with dead entries.
+    sweep_at: usize,
+    collections: usize,
+    objects_collected: usize,
+    /// Collects on every allocation, used by tests to shake out values freed too early.
+    #[cfg(test)]
+    pub stress: bool,
+}
+
+/// Counters describing the heap, returned by Interpreter::gc_stats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GcStats {
+    /// Bytes held by live strings, tuples and host objects the script created.
+    pub bytes_used: usize,
+    /// Number of live values being tracked.
+    pub tracked_objects: usize,
+    /// Number of times the cycle collector has run.
+    pub collections: usize,
+    /// Total values freed by the cycle collector.
+    pub objects_collected: usize,
+}
+
+struct Allocation {
+    value: WeakValue,
+    size: usize,
+}
+
+enum WeakValue {
+    String(Weak<String>),
+    Tuple(Weak<Vec<Value>>),
+    Host(Weak<dyn HostObject>),
+}
+
+impl WeakValue {
+    fn is_alive(&self) -> bool {
+        return match self {
+            WeakValue::String(string) => string.strong_count() > 0,
+            WeakValue::Tuple(values) => values.strong_count() > 0,
+            WeakValue::Host(object) => object.strong_count() > 0,
+        };
+    }
+
+    fn address(&self) -> usize {
+        return match self {
+            WeakValue::String(string) => string.as_ptr() as *const () as usize,
+            WeakValue::Tuple(values) => values.as_ptr() as *const () as usize,
+            WeakValue::Host(object) => object.as_ptr() as *const () as usize,
+        };
+    }
+}
+
+/// Tracked value that can hold other values, and so can be part of a cycle.
+enum Container {
+    Tuple(Rc<Vec<Value>>),
+    Host(Rc<dyn HostObject>),
+}
+
+impl Container {
+    fn address(&self) -> usize {
+        return match self {
+            Container::Tuple(values) => Rc::as_ptr(values) as *const () as usize,
+            Container::Host(object) => Rc::as_ptr(object) as *const () as usize,
+        };
+    }
+
+    fn strong_count(&self) -> usize {
+        return match self {
+            Container::Tuple(values) => Rc::strong_count(values),
+            Container::Host(object) => Rc::strong_count(object),
+        };
+    }
+
+    fn children(&self, visit: &mut dyn FnMut(&Value)) {
+        match self {
+            Container::Tuple(values) => values.iter().for_each(visit),
+            Container::Host(object) => object.trace(visit),
+        }
+    }
+}
+
+/// Address of the allocation behind a value that can hold other values.
+fn container_address(value: &Value) -> Option<usize> {
+    return match value {
+        Value::Tuple(values) => Some(Rc::as_ptr(values) as *const () as usize),
+        Value::Host(object) => Some(Rc::as_ptr(object) as *const () as usize),
+        _ => None,
+    };
+}
+
+const MIN_SWEEP_AT: usize = 256;
+
+impl Heap {
+    pub fn new() -> Self {
+        Self {
+            allocations: vec![],
+            addresses: HashMap::new(),
+            bytes: 0,
+            limit: None,
+            sweep_at: MIN_SWEEP_AT,
+            collections: 0,
+            objects_collected: 0,
+            #[cfg(test)]
+            stress: false,
+        }
+    }
+
+    /// Tracks a newly created value. </br>
+    /// Returns false if it takes the heap over its limit, in which case it isn't tracked.
+    pub fn track(&mut self, value: &Value) -> bool {
+        let (weak, size) = match value {
+            Value::String(string) => (WeakValue::String(Rc::downgrade(string)), string_size(string)),
+            Value::Tuple(values) => (WeakValue::Tuple(Rc::downgrade(values)), tuple_size(values)),
+            Value::Host(object) => (WeakValue::Host(Rc::downgrade(object)), RC_HEADER + size_of_val(object.as_ref())),
+            _ => return true,
+        };
+        #[cfg(test)]
+        if self.stress {
+            self.collect();
+        }
+        if let Some(limit) = self.limit {
+            if self.bytes + size > limit {
+                self.collect();
+                if self.bytes + size > limit { return false; }
+            }
+        }
+
+        self.bytes += size;
+        self.addresses.insert(weak.address(), self.allocations.len());
+        self.allocations.push(Allocation { value: weak, size });
+        if self.allocations.len() >= self.sweep_at {
+            self.collect();
+        }
+        return true;
+    }
+
+    /// Checks that bytes more could be allocated without going over the limit, collecting first if they wouldn't fit. </br>
+    /// Nothing is tracked, so this can be used before building a value to avoid allocating past the limit.
+    pub fn reserve(&mut self, bytes: usize) -> bool {
+        let Some(limit) = self.limit else { return true };
+        if self.bytes.saturating_add(bytes) <= limit { return true; }
+        self.collect();
+        return self.bytes.saturating_add(bytes) <= limit;
+    }
+
+    pub fn is_tracked(&self, value: &Value) -> bool {
+        let address = match value {
+            Value::String(string) => Rc::as_ptr(string) as *const () as usize,
+            Value::Tuple(values) => Rc::as_ptr(values) as *const () as usize,
+            Value::Host(object) => Rc::as_ptr(object) as *const () as usize,
+            _ => return false,
+        };
+        // a freed allocation's address can be reused before it's swept, which is a different value
+        return self.addresses.get(&address).is_some_and(|index| self.allocations[*index].value.is_alive());
+    }
+
+    /// Bytes held by allocations that are still alive.
+    pub fn bytes_used(&mut self) -> usize {
+        self.sweep();
+        return self.bytes;
+    }
+
+    pub fn stats(&mut self) -> GcStats {
+        self.sweep();
+        return GcStats {
+            bytes_used: self.bytes,
+            tracked_objects: self.allocations.len(),
+            collections: self.collections,
+            objects_collected: self.objects_collected,
+        };
+    }
+
+    /// Frees values only kept alive by reference cycles, returning how many were freed. </br>
+    /// Anything referenced from outside the tracked values (the stack, globals, call frames, host handles) is a root.
+    /// Values reachable from a root are kept, the rest are unreachable cycles and get broken up.
+    pub fn collect(&mut self) -> usize {
+        self.sweep();
+        self.collections += 1;
+
+        let containers: Vec<Container> = self.allocations.iter().filter_map(|allocation| match &allocation.value {
+            WeakValue::Tuple(values) => values.upgrade().map(Container::Tuple),
+            WeakValue::Host(object) => object.upgrade().map(Container::Host),
+            WeakValue::String(_) => None,
+        }).collect();
+        let indices: HashMap<usize, usize> = containers.iter().enumerate().map(|(i, container)| (container.address(), i)).collect();
+
+        // references each container gets from other tracked containers
+        let mut internal_counts = vec![0; containers.len()];
+        for container in containers.iter() {
+            container.children(&mut |child| {
+                if let Some(i) = container_address(child).and_then(|address| indices.get(&address)) {
+                    internal_counts[*i] += 1;
+                }
+            });
+        }
+
+        // containers with references the tracked values don't account for are held by something outside, so are roots
+        let mut reachable = vec![false; containers.len()];
+        let mut worklist = vec![];
+        for (i, container) in containers.iter().enumerate() {
+            // one reference is the upgrade held in containers
+            if container.strong_count() - 1 > internal_counts[i] {
+                reachable[i] = true;
+                worklist.push(i);
+            }
+        }
+        while let Some(i) = worklist.pop() {
+            containers[i].children(&mut |child| {
+                if let Some(&child_i) = container_address(child).and_then(|address| indices.get(&address)) {
+                    if reachable[child_i] == false {
+                        reachable[child_i] = true;
+                        worklist.push(child_i);
+                    }
+                }
+            });
+        }
+
+        let mut collected = 0;
+        for (i, container) in containers.iter().enumerate() {
+            if reachable[i] { continue; }
+            collected += 1;
+            // tuples can't be changed, they're freed once the host objects holding them let go
+            if let Container::Host(object) = container {
+                object.clear_references();
+            }
+        }
+        drop(containers);
+
+        self.objects_collected += collected;
+        self.sweep();
+        return collected;
+    }
+
+    /// Forgets freed allocations.
+    fn sweep(&mut self) {
+        self.allocations.retain(|allocation| allocation.value.is_alive());
+        self.addresses = self.allocations.iter().enumerate().map(|(index, allocation)| (allocation.value.address(), index)).collect();
+        self.bytes = self.allocations.iter().map(|allocation| allocation.size).sum();
+        self.sweep_at = (self.allocations.len() * 2).max(MIN_SWEEP_AT);
+    }
+}
+
+/// Reference counts stored alongside every Rc allocation.
+const RC_HEADER: usize = 2 * size_of::<usize>();
+
+fn string_size(string: &String) -> usize {
+    return RC_HEADER + size_of::<String>() + string.capacity();
+}
+
+fn tuple_size(values: &Vec<Value>) -> usize {
+    return RC_HEADER + size_of::<Vec<Value>>() + values.capacity() * size_of::<Value>();
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{heap::{Heap, string_size}, value::{HostObject, Value}};
+
+    #[test]
+    fn freed_values_stop_counting() {
+        let mut heap = Heap::new();
+        let string = Value::String(Rc::new("hello".to_owned()));
+        let tuple = Value::Tuple(Rc::new(vec![Value::Null, Value::Null]));
+        assert!(heap.track(&string));
+        assert!(heap.track(&tuple));
+        assert!(heap.track(&Value::Number(1.0)));
+
+        let both = heap.bytes_used();
+        drop(tuple);
+        assert!(heap.bytes_used() < both);
+        drop(string);
+        assert_eq!(0, heap.bytes_used());
+    }
+
+    #[test]
+    fn limit() {
+        let mut heap = Heap::new();
+        let string = Rc::new("hello".to_owned());
+        heap.limit = Some(string_size(&string) * 2);
+
+        let first = Value::String(string.clone());
+        assert!(heap.track(&first));
+        assert!(heap.track(&Value::String(Rc::new("hello".to_owned()))));
+        assert!(heap.track(&Value::String(Rc::new("hello".to_owned()))));
+        let second = Value::String(Rc::new("hello".to_owned()));
+        assert!(heap.track(&second));
+        assert!(heap.track(&Value::String(Rc::new("hello".to_owned()))) == false);
+
+        assert!(heap.is_tracked(&first));
+        assert!(heap.is_tracked(&Value::String(Rc::new("hello".to_owned()))) == false);
+        assert!(heap.reserve(1) == false);
+        drop(second);
+        assert!(heap.reserve(string_size(&string)));
+    }
+
+    struct Cell {
+        value: RefCell<Value>,
+    }
+
+    impl HostObject for Cell {
+        fn trace(&self, visit: &mut dyn FnMut(&Value)) {
+            visit(&self.value.borrow());
+        }
+
+        fn clear_references(&self) {
+            *self.value.borrow_mut() = Value::Null;
+        }
+    }
+
+    fn cell(heap: &mut Heap) -> (Rc<Cell>, Value) {
+        let cell = Rc::new(Cell { value: RefCell::new(Value::Null) });
+        let value = Value::Host(cell.clone());
+        heap.track(&value);
+        return (cell, value);
+    }
+
+    #[test]
+    fn collect_cycles() {
+        let mut heap = Heap::new();
+
+        // a -> (a, 1), unreachable once dropped
+        let (a, a_value) = cell(&mut heap);
+        let tuple = Value::Tuple(Rc::new(vec![a_value.clone(), Value::Number(1.0)]));
+        heap.track(&tuple);
+        *a.value.borrow_mut() = tuple;
+        let weak_a = Rc::downgrade(&a);
+        drop(a);
+        drop(a_value);
+
+        // b -> c -> b, kept alive by root
+        let (b, b_value) = cell(&mut heap);
+        let (c, c_value) = cell(&mut heap);
+        *b.value.borrow_mut() = c_value.clone();
+        *c.value.borrow_mut() = b_value.clone();
+        drop(c);
+        drop(c_value);
+
+        assert_eq!(2, heap.collect());
+        assert!(weak_a.upgrade().is_none());
+        assert!(b.value.borrow().downcast_host::<Cell>().is_some());
+        let stats = heap.stats();
+        assert_eq!(2, stats.tracked_objects);
+        assert_eq!(2, stats.objects_collected);
+
+        drop(b_value);
+        drop(b);
+        assert_eq!(2, heap.collect());
+        assert_eq!(0, heap.bytes_used());
+    }
+}
@@ -0,0 +1,78 @@
+use std::{borrow::Borrow, collections::HashSet, hash::{Hash, Hasher}, rc::Rc};
+
+/// Hands out one shared Rc for every distinct string, so identical strings share storage. </br>
+/// The compiler interns string literals and passes the interner on to the vm, which reuses them for strings it makes with the same contents.
+/// Rc compares pointers before contents, so equal interned strings compare without looking at their bytes.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Interner {
+    strings: HashSet<Interned>,
+}
+
+/// Hashes and compares by contents, and borrows as a str so lookups don't need to allocate.
+#[derive(Debug, PartialEq, Eq)]
+struct Interned(Rc<String>);
+
+impl Hash for Interned {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.as_str().hash(state);
+    }
+}
+
+impl Borrow<str> for Interned {
+    fn borrow(&self) -> &str {
+        return self.0.as_str();
+    }
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self { strings: HashSet::new() }
+    }
+
+    /// Returns the shared string, creating it the first time it's seen.
+    pub fn intern(&mut self, string: &str) -> Rc<String> {
+        // every literal gets its own copy, as before interning, for benches to compare against
+        if cfg!(feature = "no-interning") {
+            return Rc::new(string.to_owned());
+        }
+        if let Some(interned) = self.get(string) {
+            return interned;
+        }
+        let interned = Rc::new(string.to_owned());
+        self.insert(interned.clone());
+        return interned;
+    }
+
+    pub fn get(&self, string: &str) -> Option<Rc<String>> {
+        return self.strings.get(string).map(|interned| interned.0.clone());
+    }
+
+    /// Adds a string, other strings with the same contents will share it from now on.
+    pub fn insert(&mut self, string: Rc<String>) {
+        self.strings.insert(Interned(string));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use crate::interner::Interner;
+
+    #[test]
+    #[cfg(not(feature = "no-interning"))]
+    fn intern() {
+        let mut interner = Interner::new();
+        let a = interner.intern("hello");
+        let b = interner.intern("hello");
+        let c = interner.intern("world");
+        assert!(Rc::ptr_eq(&a, &b));
+        assert!(!Rc::ptr_eq(&a, &c));
+        assert_eq!(2, interner.strings.len());
+
+        assert!(interner.get("new").is_none());
+        let new = Rc::new("new".to_owned());
+        interner.insert(new.clone());
+        assert!(Rc::ptr_eq(&new, &interner.get("new").unwrap()));
+    }
+}
@@ -1,15 +1,84 @@
-use std::{io::{self, Write}, rc::Rc, time::{SystemTime, UNIX_EPOCH}};
+use std::{cell::RefCell, rc::Rc, time::Duration};
 
-use rand::Rng;
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::{IndexedRandom, SliceRandom}};
 
-use crate::{compiler::Compiler, value::{NativeFunction, Value}, vm::VM};
+use crate::{cancel::CancelToken, capabilities::Capabilities, clock::{Clock, SystemClock}, compiler::Compiler, convert::IntoNative, heap::GcStats, io::{IoHandler, StdIo}, native_module::NativeModule, value::{NativeFunction, Value}, vm::{DEFAULT_MAX_FRAMES, DEFAULT_MAX_STACK, NativeCtx, VM}};
 
-pub struct Interpreter {
-    vm: VM
+/// Random number generator shared between the interpreter and the random natives.
+type SharedRng = Rc<RefCell<StdRng>>;
+/// Clock shared between the interpreter and the time natives.
+type SharedClock = Rc<RefCell<Box<dyn Clock>>>;
+/// Io handler shared between the interpreter and the io natives.
+type SharedIo = Rc<RefCell<Box<dyn IoHandler>>>;
+
+/// Host state the built-in natives read from, shared with the interpreter.
+#[derive(Clone)]
+struct Builtins {
+    rng: SharedRng,
+    clock: SharedClock,
+    io: SharedIo,
+}
+
+/// Runs a compiled script. </br>
+/// T is host data owned by the interpreter that natives can reach through their NativeCtx.
+pub struct Interpreter<T: 'static = ()> {
+    vm: VM,
+    builtins: Builtins,
+    data: T,
+}
+
+/// Configures an interpreter before compiling its source.
+pub struct InterpreterBuilder<T: 'static = ()> {
+    natives: Vec<NativeFunction>,
+    modules: Vec<NativeModule>,
+    capabilities: Capabilities,
+    flat_builtins: bool,
+    io: Box<dyn IoHandler>,
+    cancel_token: CancelToken,
+    max_frames: usize,
+    max_stack: usize,
+    memory_limit: Option<usize>,
+    data: T,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct RuntimeError {
-    pub message: &'static str
+    pub message: String,
+    pub kind: RuntimeErrorKind,
+    /// Where each running function was when the error happened, innermost first. </br>
+    /// Functions that made tail calls note how many frames they replaced, since those no longer show up.
+    pub trace: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuntimeErrorKind {
+    /// The script did something invalid, or a native returned an error.
+    Script,
+    /// The script was stopped through its CancelToken.
+    Cancelled,
+    /// Calls went deeper than the interpreter's call depth or stack size limits.
+    StackOverflow,
+    /// Strings and tuples made by the script took more memory than the interpreter's limit.
+    OutOfMemory,
+}
+
+impl RuntimeError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            kind: RuntimeErrorKind::Script,
+            trace: vec![],
+        }
+    }
+}
+
+/// How far a budgeted run got.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunStatus {
+    /// The script ran to the end.
+    Finished,
+    /// The budget ran out, running again resumes where it stopped.
+    Paused,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -20,22 +89,155 @@ pub struct CompilerError {
     pub message: String
 }
 
-impl Interpreter {
-    pub fn new(source: String, natives: Vec<NativeFunction>) -> Result<Self, Vec<CompilerError>> {
+impl InterpreterBuilder {
+    pub fn new() -> Self {
+        Self {
+            natives: vec![],
+            modules: vec![],
+            capabilities: Capabilities::console(),
+            flat_builtins: false,
+            io: Box::new(StdIo),
+            cancel_token: CancelToken::new(),
+            max_frames: DEFAULT_MAX_FRAMES,
+            max_stack: DEFAULT_MAX_STACK,
+            memory_limit: None,
+            data: (),
+        }
+    }
+}
+
+impl Default for InterpreterBuilder {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl<T: 'static> InterpreterBuilder<T> {
+    /// Sets the host data the interpreter owns and hands to natives.
+    pub fn data<U: 'static>(self, data: U) -> InterpreterBuilder<U> {
+        return InterpreterBuilder {
+            natives: self.natives,
+            modules: self.modules,
+            capabilities: self.capabilities,
+            flat_builtins: self.flat_builtins,
+            io: self.io,
+            cancel_token: self.cancel_token,
+            max_frames: self.max_frames,
+            max_stack: self.max_stack,
+            memory_limit: self.memory_limit,
+            data,
+        };
+    }
+
+    /// Adds a host native, building fails if a built-in global already has its name.
+    pub fn native(mut self, native: NativeFunction) -> Self {
+        self.natives.push(native);
+        return self;
+    }
+
+    /// Adds a Rust closure as a native, with the arity and argument type checks derived from its signature. </br>
+    /// e.g. `register_fn("add", |a: f64, b: f64| a + b)`
+    pub fn register_fn<Args>(self, name: &str, function: impl IntoNative<Args>) -> Self {
+        return self.native(NativeFunction::from_fn(name, function));
+    }
+
+    pub fn natives(mut self, natives: Vec<NativeFunction>) -> Self {
+        self.natives.extend(natives);
+        return self;
+    }
+
+    /// Exposes a module to scripts, building fails if a built-in module already has its name.
+    pub fn module(mut self, module: NativeModule) -> Self {
+        self.modules.push(module);
+        return self;
+    }
+
+    /// Sets which built-in natives are installed. Defaults to Capabilities::console().
+    pub fn capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        return self;
+    }
+
+    /// Also installs every built-in module member as a global, like `print` or `sqrt`, for scripts written before modules. Off by default.
+    pub fn flat_builtins(mut self, flat_builtins: bool) -> Self {
+        self.flat_builtins = flat_builtins;
+        return self;
+    }
+
+    /// Uses an existing token for cancelling the script, such as one shared with a watchdog.
+    pub fn cancel_token(mut self, cancel_token: CancelToken) -> Self {
+        self.cancel_token = cancel_token;
+        return self;
+    }
+
+    /// Sets how deep calls can nest before failing with a stack overflow. Defaults to 1024.
+    pub fn max_call_depth(mut self, max_frames: usize) -> Self {
+        self.max_frames = max_frames;
+        return self;
+    }
+
+    /// Sets how many values the stack can hold before calls fail with a stack overflow. Defaults to 65536.
+    pub fn max_stack_size(mut self, max_stack: usize) -> Self {
+        self.max_stack = max_stack;
+        return self;
+    }
+
+    /// Caps the bytes held by strings and tuples the script creates, going over is a runtime error. Unlimited by default.
+    pub fn memory_limit(mut self, bytes: usize) -> Self {
+        self.memory_limit = Some(bytes);
+        return self;
+    }
+
+    /// Redirects print, input and clear to the handler. Defaults to StdIo.
+    pub fn io(mut self, io: impl IoHandler + 'static) -> Self {
+        self.io = Box::new(io);
+        return self;
+    }
+
+    pub fn build(self, source: String) -> Result<Interpreter<T>, Vec<CompilerError>> {
         let mut compiler = Compiler::new(&source);
+        let builtins = Builtins {
+            rng: Rc::new(RefCell::new(StdRng::from_os_rng())),
+            clock: Rc::new(RefCell::new(Box::new(SystemClock::new()))),
+            io: Rc::new(RefCell::new(self.io)),
+        };
 
-        add_builtin_natives(&mut compiler);
+        add_builtin_natives(&mut compiler, &builtins, self.capabilities, self.flat_builtins);
+
+        // host names that would hide a built-in are refused, rather than quietly changing what scripts call
+        let mut collisions = vec![];
+        for native in self.natives.iter() {
+            if compiler.has_global(&native.name) {
+                collisions.push(format!("Host native '{}' has the same name as a built-in.", native.name));
+            }
+        }
+        for module in self.modules.iter() {
+            if compiler.has_module(&module.name) {
+                collisions.push(format!("Host module '{}' has the same name as a built-in module.", module.name));
+            }
+        }
+        if collisions.len() > 0 {
+            return Err(collisions.into_iter().map(|message| CompilerError { line: 0, start: 0, len: 0, message }).collect());
+        }
 
-        for native in natives.into_iter() {
+        for native in self.natives.into_iter() {
             compiler.add_native(native);
         }
+        for module in self.modules.into_iter() {
+            compiler.add_module(module);
+        }
 
         match compiler.compile() {
             Ok(compiler_out) => {
-                let vm = VM::new(compiler_out);
+                let mut vm = VM::new(compiler_out, self.cancel_token);
+                vm.max_frames = self.max_frames;
+                vm.max_stack = self.max_stack;
+                vm.heap.limit = self.memory_limit;
 
-                let interpreter = Self {
+                let interpreter = Interpreter {
                     vm: vm,
+                    builtins: builtins,
+                    data: self.data,
                 };
                 return Ok(interpreter);
 
@@ -44,83 +246,94 @@ impl Interpreter {
                 return Err(compiler_errors);
             },
         }
+    }
+}
+
+impl Interpreter {
+    pub fn new(source: String, natives: Vec<NativeFunction>) -> Result<Self, Vec<CompilerError>> {
+        return InterpreterBuilder::new().natives(natives).build(source);
+    }
 
+    pub fn builder() -> InterpreterBuilder {
+        return InterpreterBuilder::new();
     }
+}
+
+impl<T: 'static> Interpreter<T> {
     pub fn run(&mut self) -> Result<(), RuntimeError> {
-        return match self.vm.run() {
+        return match self.vm.run(&mut self.data) {
             Ok(()) => Ok(()),
             Err(runtime_err) => Err(runtime_err),
         };
     }
 
+    /// Runs at most budget instructions. </br>
+    /// Returns Paused if the budget ran out first, calling it again carries on from there.
+    pub fn run_with_budget(&mut self, budget: usize) -> Result<RunStatus, RuntimeError> {
+        return self.vm.run_with_budget(budget, &mut self.data);
+    }
+
+    /// Bytes currently held by strings, tuples and host objects the script created.
+    pub fn memory_used(&mut self) -> usize {
+        return self.vm.heap.bytes_used();
+    }
+
+    /// Frees values only kept alive by reference cycles, returning how many were freed. </br>
+    /// The collector also runs by itself as allocations pile up and before giving up on the memory limit.
+    pub fn collect_garbage(&mut self) -> usize {
+        return self.vm.collect_garbage();
+    }
+
+    pub fn gc_stats(&mut self) -> GcStats {
+        return self.vm.heap.stats();
+    }
+
+    /// Token that stops the script when cancelled, can be cloned and shared between threads.
+    pub fn cancel_token(&self) -> CancelToken {
+        return self.vm.cancel_token.clone();
+    }
+
     /// Returns boolean for if there's a next step </br>
     /// False means there will be no next step.
     pub fn step(&mut self) -> Result<bool, RuntimeError> {
-        return self.vm.step();
+        return self.vm.step(&mut self.data);
     }
 
-}
-
-    fn add_builtin_natives(compiler: &mut Compiler) {
-        let time = NativeFunction {
-            name: "time".to_owned(),
-            arity: 0,
-            function: {
-                fn time(_: &[Value]) -> Value {
-                    let secs = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs_f64();
-                    Value::Number(secs)
-                }
-                Box::new(time)
-            },
-        };
+    /// Reseeds the random number generator used by the random natives. </br>
+    /// The same seed always produces the same sequence of random values.
+    pub fn set_seed(&mut self, seed: u64) {
+        *self.builtins.rng.borrow_mut() = StdRng::seed_from_u64(seed);
+    }
 
-        let print = NativeFunction {
-            name: "print".to_owned(),
-            arity: 1,
-            function: {
-                fn print(vals: &[Value]) -> Value {
-                    println!("{}", vals[0]);
-                    return Value::Null;
-                }
-                Box::new(print)
-            },
-        };
+    /// Replaces the clock used by the time natives, such as with a VirtualClock for deterministic time.
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) {
+        *self.builtins.clock.borrow_mut() = Box::new(clock);
+    }
 
-        let random_range = NativeFunction {
-            name: "random_range".to_owned(),
-            arity: 2,
-            function: {
-                fn random_range(vals: &[Value]) -> Value {
-                    return match (vals[0].clone(), vals[1].clone()) {
-                        (Value::Number(min), Value::Number(max)) => {
-                            let mut rng = rand::rng();
-                            return Value::Number(rng.random_range(min..=max) as f64);
-                        },
-                        _ => Value::Null
-                    };
+    pub fn data(&self) -> &T {
+        return &self.data;
+    }
 
-                }
+    pub fn data_mut(&mut self) -> &mut T {
+        return &mut self.data;
+    }
 
-                Box::new(random_range)
-            },
-        };
+}
 
+    fn add_builtin_natives(compiler: &mut Compiler, builtins: &Builtins, capabilities: Capabilities, flat_builtins: bool) {
         let to_number = NativeFunction {
             name: "number".to_owned(),
             arity: 1,
             function: {
-                fn number(vals: &[Value]) -> Value {
-                    return match vals[0].clone() {
+                fn number(_: &mut NativeCtx, vals: &[Value]) -> Result<Value, RuntimeError> {
+                    return Ok(match vals[0].clone() {
                         Value::Number(number) => Value::Number(number),
                         Value::String(string) => match string.parse::<f64>() {
                             Ok(number) => Value::Number(number),
                             Err(_) => Value::Null,
                         },
                         _ => Value::Null
-                    };
+                    });
                 }
                 Box::new(number)
             },
@@ -130,66 +343,1375 @@ impl Interpreter {
             name: "string".to_owned(),
             arity: 1,
             function: {
-                fn to_string(vals: &[Value]) -> Value {
-                    match vals[0].clone() {
+                fn to_string(_: &mut NativeCtx, vals: &[Value]) -> Result<Value, RuntimeError> {
+                    return Ok(match vals[0].clone() {
                         Value::String(s) => Value::String(s),
                         Value::Number(n) => Value::String(Rc::new(n.to_string())),
                         Value::Bool(b) => Value::String(Rc::new(b.to_string())),
                         Value::Null => Value::String(Rc::new("null".to_owned())),
+                        Value::Tuple(_) => Value::String(Rc::new(vals[0].to_string())),
                         _ => Value::Null,
-                    }
+                    });
                 }
                 Box::new(to_string)
             },
         };
 
-        let get_input = NativeFunction {
-            name: "input".to_owned(),
+        let round = NativeFunction {
+            name: "round".to_owned(),
             arity: 1,
             function: {
-                fn input(vals: &[Value]) -> Value {
-                    print!("{}", vals[0].clone());
-                    io::stdout().flush().unwrap();
-                    let mut input = String::new();
-                    io::stdin().read_line(&mut input).unwrap();
-                    return Value::String(Rc::new(input.trim().to_string()));
+                fn round(_: &mut NativeCtx, vals: &[Value]) -> Result<Value, RuntimeError> {
+                    return Ok(match vals[0].clone() {
+                        Value::Number(num) => Value::Number(num.round()),
+                        _ => Value::Null
+                    });
                 }
-                Box::new(input)
+                Box::new(round)
             },
         };
 
+        let len = NativeFunction {
+            name: "len".to_owned(),
+            arity: 1,
+            function: {
+                fn len(_: &mut NativeCtx, vals: &[Value]) -> Result<Value, RuntimeError> {
+                    return Ok(match vals[0].clone() {
+                        Value::String(string) => Value::Number(string.chars().count() as f64),
+                        Value::Tuple(values) => Value::Number(values.len() as f64),
+                        _ => Value::Null
+                    });
+                }
+                Box::new(len)
+            },
+        };
+
+        compiler.add_native(to_number);
+        compiler.add_native(to_string);
+        compiler.add_native(round);
+        compiler.add_native(len);
+
+        let modules = [
+            (string_module(), Capabilities::NONE, "none"),
+            (math_module(), Capabilities::NONE, "none"),
+            (random_module(&builtins.rng), Capabilities::RANDOM, "random"),
+            (time_module(&builtins.clock), Capabilities::TIME, "time"),
+            (io_module(&builtins.io), Capabilities::CONSOLE, "console"),
+            (fs_module(), Capabilities::FILESYSTEM, "filesystem"),
+        ];
+        for (module, required, capability_name) in modules.into_iter() {
+            if capabilities.contains(required) == false {
+                for (name, _) in module.members() {
+                    compiler.add_unavailable(name, format!("'{}' needs the {} capability.", name, capability_name));
+                }
+                compiler.add_unavailable(&module.name, format!("'{}' needs the {} capability.", module.name, capability_name));
+                continue;
+            }
+            for (name, value) in module.members() {
+                if flat_builtins {
+                    compiler.add_constant(name, value.clone());
+                }
+                else {
+                    compiler.add_unavailable(name, format!("'{}' is in the {} module, use {}.{} or import it.", name, module.name, module.name, name));
+                }
+            }
+            compiler.add_module(module);
+        }
+    }
+
+    fn string_module() -> NativeModule {
+        let mut module = NativeModule::new("string");
+
+        let upper = NativeFunction {
+            name: "upper".to_owned(),
+            arity: 1,
+            function: {
+                fn upper(_: &mut NativeCtx, vals: &[Value]) -> Result<Value, RuntimeError> {
+                    let string = string_arg("upper", vals, 0)?;
+                    return Ok(Value::String(Rc::new(string.to_uppercase())));
+                }
+                Box::new(upper)
+            },
+        };
+
+        let lower = NativeFunction {
+            name: "lower".to_owned(),
+            arity: 1,
+            function: {
+                fn lower(_: &mut NativeCtx, vals: &[Value]) -> Result<Value, RuntimeError> {
+                    let string = string_arg("lower", vals, 0)?;
+                    return Ok(Value::String(Rc::new(string.to_lowercase())));
+                }
+                Box::new(lower)
+            },
+        };
+
+        let trim = NativeFunction {
+            name: "trim".to_owned(),
+            arity: 1,
+            function: {
+                fn trim(_: &mut NativeCtx, vals: &[Value]) -> Result<Value, RuntimeError> {
+                    let string = string_arg("trim", vals, 0)?;
+                    return Ok(Value::String(Rc::new(string.trim().to_owned())));
+                }
+                Box::new(trim)
+            },
+        };
+
+        let split = NativeFunction {
+            name: "split".to_owned(),
+            arity: 2,
+            function: {
+                fn split(ctx: &mut NativeCtx, vals: &[Value]) -> Result<Value, RuntimeError> {
+                    let string = string_arg("split", vals, 0)?;
+                    let separator = string_arg("split", vals, 1)?;
+                    let count = match separator.is_empty() {
+                        true => string.chars().count(),
+                        false => string.matches(separator).count() + 1,
+                    };
+                    ctx.reserve(string.len() + count * size_of::<Value>())?;
+                    let parts: Vec<Value> = if separator.is_empty() {
+                        string.chars().map(|c| Value::String(Rc::new(c.to_string()))).collect()
+                    }
+                    else {
+                        string.split(separator).map(|part| Value::String(Rc::new(part.to_owned()))).collect()
+                    };
+                    return Ok(Value::Tuple(Rc::new(parts)));
+                }
+                Box::new(split)
+            },
+        };
+
+        let join = NativeFunction {
+            name: "join".to_owned(),
+            arity: 2,
+            function: {
+                fn join(ctx: &mut NativeCtx, vals: &[Value]) -> Result<Value, RuntimeError> {
+                    let Value::Tuple(parts) = vals[0].clone() else {
+                        return Err(RuntimeError::new("join expects a tuple for argument 1."));
+                    };
+                    let separator = string_arg("join", vals, 1)?;
+                    let parts: Vec<String> = parts.iter().map(|part| part.to_string()).collect();
+                    let length = parts.iter().map(String::len).sum::<usize>() + separator.len() * parts.len().saturating_sub(1);
+                    ctx.reserve(length)?;
+                    return Ok(Value::String(Rc::new(parts.join(separator))));
+                }
+                Box::new(join)
+            },
+        };
+
+        let replace = NativeFunction {
+            name: "replace".to_owned(),
+            arity: 3,
+            function: {
+                fn replace(ctx: &mut NativeCtx, vals: &[Value]) -> Result<Value, RuntimeError> {
+                    let string = string_arg("replace", vals, 0)?;
+                    let from = string_arg("replace", vals, 1)?;
+                    let to = string_arg("replace", vals, 2)?;
+                    // an empty from matches between every character and at both ends
+                    let matches = string.matches(from).count();
+                    ctx.reserve(string.len() - matches * from.len() + matches * to.len())?;
+                    return Ok(Value::String(Rc::new(string.replace(from, to))));
+                }
+                Box::new(replace)
+            },
+        };
+
+        let find = NativeFunction {
+            name: "find".to_owned(),
+            arity: 2,
+            function: {
+                fn find(_: &mut NativeCtx, vals: &[Value]) -> Result<Value, RuntimeError> {
+                    let string = string_arg("find", vals, 0)?;
+                    let sub_string = string_arg("find", vals, 1)?;
+                    return Ok(match string.find(sub_string) {
+                        // byte offset to character index, matching string indexing
+                        Some(byte_index) => Value::Number(string[..byte_index].chars().count() as f64),
+                        None => Value::Number(-1.0),
+                    });
+                }
+                Box::new(find)
+            },
+        };
+
+        let starts_with = NativeFunction {
+            name: "starts_with".to_owned(),
+            arity: 2,
+            function: {
+                fn starts_with(_: &mut NativeCtx, vals: &[Value]) -> Result<Value, RuntimeError> {
+                    let string = string_arg("starts_with", vals, 0)?;
+                    let prefix = string_arg("starts_with", vals, 1)?;
+                    return Ok(Value::Bool(string.starts_with(prefix)));
+                }
+                Box::new(starts_with)
+            },
+        };
+
+        let ends_with = NativeFunction {
+            name: "ends_with".to_owned(),
+            arity: 2,
+            function: {
+                fn ends_with(_: &mut NativeCtx, vals: &[Value]) -> Result<Value, RuntimeError> {
+                    let string = string_arg("ends_with", vals, 0)?;
+                    let suffix = string_arg("ends_with", vals, 1)?;
+                    return Ok(Value::Bool(string.ends_with(suffix)));
+                }
+                Box::new(ends_with)
+            },
+        };
+
+        let repeat = NativeFunction {
+            name: "repeat".to_owned(),
+            arity: 2,
+            function: {
+                fn repeat(ctx: &mut NativeCtx, vals: &[Value]) -> Result<Value, RuntimeError> {
+                    let string = string_arg("repeat", vals, 0)?;
+                    let count = match vals[1] {
+                        Value::Number(count) if count >= 0.0 && count.fract() == 0.0 => count,
+                        _ => return Err(RuntimeError::new("repeat expects a non-negative integer for argument 2.")),
+                    };
+                    // strings can't hold more than isize::MAX bytes
+                    let length = string.len().checked_mul(count as usize).filter(|length| *length <= isize::MAX as usize);
+                    let Some(length) = length else {
+                        return Err(RuntimeError::new("repeat result is too large."));
+                    };
+                    ctx.reserve(length)?;
+                    return Ok(Value::String(Rc::new(string.repeat(count as usize))));
+                }
+                Box::new(repeat)
+            },
+        };
+
+        let format = NativeFunction {
+            name: "format".to_owned(),
+            arity: 2,
+            function: {
+                /// Replaces each '{}' in the template with the next value. </br>
+                /// Values can be a tuple, or any single value for one placeholder.
+                fn format(ctx: &mut NativeCtx, vals: &[Value]) -> Result<Value, RuntimeError> {
+                    let template = string_arg("format", vals, 0)?;
+                    let values = match vals[1].clone() {
+                        Value::Tuple(values) => values,
+                        value => Rc::new(vec![value]),
+                    };
+
+                    let placeholders = template.matches("{}").count();
+                    if values.len() < placeholders {
+                        return Err(RuntimeError::new(format!("format expects a value for each {{}}, but got {} for {}.", values.len(), placeholders)));
+                    }
+                    let values: Vec<String> = values.iter().take(placeholders).map(|value| value.to_string()).collect();
+                    ctx.reserve(template.len() + values.iter().map(String::len).sum::<usize>())?;
+
+                    let mut formatted = String::new();
+                    let mut values = values.iter();
+                    let mut pieces = template.split("{}").peekable();
+                    while let Some(piece) = pieces.next() {
+                        formatted.push_str(piece);
+                        if pieces.peek().is_none() { break; }
+                        formatted.push_str(values.next().unwrap());
+                    }
+                    return Ok(Value::String(Rc::new(formatted)));
+                }
+                Box::new(format)
+            },
+        };
+
+        module.add_native(upper);
+        module.add_native(lower);
+        module.add_native(trim);
+        module.add_native(split);
+        module.add_native(join);
+        module.add_native(replace);
+        module.add_native(find);
+        module.add_native(starts_with);
+        module.add_native(ends_with);
+        module.add_native(repeat);
+        module.add_native(format);
+
+        return module;
+    }
+
+    fn math_module() -> NativeModule {
+        let mut module = NativeModule::new("math");
+
+        module.add_native(unary_math_native("floor", f64::floor));
+        module.add_native(unary_math_native("ceil", f64::ceil));
+        module.add_native(unary_math_native("abs", f64::abs));
+        module.add_native(unary_math_native("sqrt", f64::sqrt));
+        module.add_native(unary_math_native("sin", f64::sin));
+        module.add_native(unary_math_native("cos", f64::cos));
+        module.add_native(unary_math_native("tan", f64::tan));
+        module.add_native(unary_math_native("log", f64::ln));
+        module.add_native(unary_math_native("exp", f64::exp));
+        module.add_native(binary_math_native("pow", f64::powf));
+        module.add_native(binary_math_native("atan2", f64::atan2));
+        module.add_native(binary_math_native("min", f64::min));
+        module.add_native(binary_math_native("max", f64::max));
+
+        let clamp = NativeFunction {
+            name: "clamp".to_owned(),
+            arity: 3,
+            function: {
+                fn clamp(_: &mut NativeCtx, vals: &[Value]) -> Result<Value, RuntimeError> {
+                    let num = number_arg("clamp", vals, 0)?;
+                    let min = number_arg("clamp", vals, 1)?;
+                    let max = number_arg("clamp", vals, 2)?;
+                    if min > max {
+                        return Err(RuntimeError::new("clamp expects min to be less than or equal to max."));
+                    }
+                    return Ok(Value::Number(num.clamp(min, max)));
+                }
+                Box::new(clamp)
+            },
+        };
+        module.add_native(clamp);
+
+        module.add_constant("PI", Value::Number(std::f64::consts::PI));
+        module.add_constant("E", Value::Number(std::f64::consts::E));
+
+        return module;
+    }
+
+    fn unary_math_native(name: &'static str, apply: fn(f64) -> f64) -> NativeFunction {
+        return NativeFunction {
+            name: name.to_owned(),
+            arity: 1,
+            function: Box::new(move |_, vals| Ok(Value::Number(apply(number_arg(name, vals, 0)?)))),
+        };
+    }
+
+    fn binary_math_native(name: &'static str, apply: fn(f64, f64) -> f64) -> NativeFunction {
+        return NativeFunction {
+            name: name.to_owned(),
+            arity: 2,
+            function: Box::new(move |_, vals| Ok(Value::Number(apply(number_arg(name, vals, 0)?, number_arg(name, vals, 1)?)))),
+        };
+    }
+
+    /// Gets the argument at index as a number, or an error naming the native.
+    fn number_arg(native_name: &str, vals: &[Value], index: usize) -> Result<f64, RuntimeError> {
+        return match vals[index] {
+            Value::Number(num) => Ok(num),
+            _ => Err(RuntimeError::new(format!("{} expects number arguments.", native_name))),
+        };
+    }
+
+    fn random_module(rng: &SharedRng) -> NativeModule {
+        let mut module = NativeModule::new("random");
+
+        let seed_rng = rng.clone();
+        let seed = NativeFunction {
+            name: "seed".to_owned(),
+            arity: 1,
+            function: Box::new(move |_, vals| {
+                return match vals[0] {
+                    Value::Number(seed) if seed >= 0.0 && seed.fract() == 0.0 => {
+                        *seed_rng.borrow_mut() = StdRng::seed_from_u64(seed as u64);
+                        Ok(Value::Null)
+                    },
+                    _ => Err(RuntimeError::new("seed expects a non-negative integer.")),
+                };
+            }),
+        };
+
+        let random_rng = rng.clone();
+        let random = NativeFunction {
+            name: "random".to_owned(),
+            arity: 0,
+            function: Box::new(move |_, _| Ok(Value::Number(random_rng.borrow_mut().random::<f64>()))),
+        };
+
+        let range_rng = rng.clone();
+        let random_range = NativeFunction {
+            name: "random_range".to_owned(),
+            arity: 2,
+            function: Box::new(move |_, vals| {
+                return Ok(match (vals[0].clone(), vals[1].clone()) {
+                    // the width has to be finite as well, or the range can't be sampled
+                    (Value::Number(min), Value::Number(max)) if min <= max && (max - min).is_finite() => {
+                        Value::Number(range_rng.borrow_mut().random_range(min..=max))
+                    },
+                    _ => Value::Null
+                });
+            }),
+        };
+
+        let int_rng = rng.clone();
+        let random_int = NativeFunction {
+            name: "random_int".to_owned(),
+            arity: 2,
+            function: Box::new(move |_, vals| {
+                let min = number_arg("random_int", vals, 0)?;
+                let max = number_arg("random_int", vals, 1)?;
+                if min.fract() != 0.0 || max.fract() != 0.0 || min > max {
+                    return Err(RuntimeError::new("random_int expects integers where min is less than or equal to max."));
+                }
+                return Ok(Value::Number(int_rng.borrow_mut().random_range(min as i64..=max as i64) as f64));
+            }),
+        };
+
+        let choice_rng = rng.clone();
+        let choice = NativeFunction {
+            name: "choice".to_owned(),
+            arity: 1,
+            function: Box::new(move |_, vals| {
+                let chosen = match vals[0].clone() {
+                    Value::Tuple(values) => values.choose(&mut *choice_rng.borrow_mut()).cloned(),
+                    Value::String(string) => {
+                        let chars: Vec<char> = string.chars().collect();
+                        chars.choose(&mut *choice_rng.borrow_mut()).map(|c| Value::String(Rc::new(c.to_string())))
+                    },
+                    _ => return Err(RuntimeError::new("choice expects a tuple or string.")),
+                };
+                return chosen.ok_or_else(|| RuntimeError::new("choice cannot choose from an empty tuple or string."));
+            }),
+        };
+
+        let shuffle_rng = rng.clone();
+        let shuffle = NativeFunction {
+            name: "shuffle".to_owned(),
+            arity: 1,
+            function: Box::new(move |_, vals| {
+                // tuples are immutable, so a shuffled copy is returned
+                let Value::Tuple(values) = vals[0].clone() else {
+                    return Err(RuntimeError::new("shuffle expects a tuple."));
+                };
+                let mut shuffled = values.as_ref().clone();
+                shuffled.shuffle(&mut *shuffle_rng.borrow_mut());
+                return Ok(Value::Tuple(Rc::new(shuffled)));
+            }),
+        };
+
+        module.add_native(seed);
+        module.add_native(random);
+        module.add_native(random_range);
+        module.add_native(random_int);
+        module.add_native(choice);
+        module.add_native(shuffle);
+
+        return module;
+    }
+
+    fn time_module(clock: &SharedClock) -> NativeModule {
+        let mut module = NativeModule::new("time");
+
+        let time_clock = clock.clone();
+        let time = NativeFunction {
+            name: "time".to_owned(),
+            arity: 0,
+            function: Box::new(move |_, _| Ok(Value::Number(time_clock.borrow().time()))),
+        };
+
+        let monotonic_clock = clock.clone();
+        let monotonic = NativeFunction {
+            name: "monotonic".to_owned(),
+            arity: 0,
+            function: Box::new(move |_, _| Ok(Value::Number(monotonic_clock.borrow().monotonic()))),
+        };
+
+        let sleep_clock = clock.clone();
+        let sleep = NativeFunction {
+            name: "sleep".to_owned(),
+            arity: 1,
+            function: Box::new(move |_, vals| {
+                let seconds = number_arg("sleep", vals, 0)?;
+                // also rules out durations too long for the system clock to wait for
+                if Duration::try_from_secs_f64(seconds).is_err() {
+                    return Err(RuntimeError::new("sleep expects a non-negative number of seconds."));
+                }
+                sleep_clock.borrow().sleep(seconds);
+                return Ok(Value::Null);
+            }),
+        };
+
+        module.add_native(time);
+        module.add_native(monotonic);
+        module.add_native(sleep);
+
+        return module;
+    }
+
+    fn io_module(io: &SharedIo) -> NativeModule {
+        let mut module = NativeModule::new("io");
+
+        let print_io = io.clone();
+        let print = NativeFunction {
+            name: "print".to_owned(),
+            arity: 1,
+            function: Box::new(move |_, vals| {
+                print_io.borrow_mut().print(&vals[0].to_string());
+                return Ok(Value::Null);
+            }),
+        };
+
+        let input_io = io.clone();
+        let get_input = NativeFunction {
+            name: "input".to_owned(),
+            arity: 1,
+            function: Box::new(move |_, vals| {
+                let input = input_io.borrow_mut().input(&vals[0].to_string());
+                return Ok(Value::String(Rc::new(input)));
+            }),
+        };
+
+        let clear_io = io.clone();
         let clear = NativeFunction {
             name: "clear".to_owned(),
             arity: 0,
+            function: Box::new(move |_, _| {
+                clear_io.borrow_mut().clear();
+                return Ok(Value::Null);
+            }),
+        };
+
+        module.add_native(print);
+        module.add_native(get_input);
+        module.add_native(clear);
+
+        return module;
+    }
+
+    fn fs_module() -> NativeModule {
+        let mut module = NativeModule::new("fs");
+
+        module.add_native(NativeFunction {
+            name: "read_file".to_owned(),
+            arity: 1,
             function: {
-                fn clear(_: &[Value]) -> Value {
-                    print!("\x1B[2J\x1B[1;1H"); // basically regex so who the fuck knows
-                    return Value::Null;
+                fn read_file(_: &mut NativeCtx, vals: &[Value]) -> Result<Value, RuntimeError> {
+                    let path = string_arg("read_file", vals, 0)?;
+                    return match std::fs::read_to_string(path) {
+                        Ok(contents) => Ok(Value::String(Rc::new(contents))),
+                        Err(err) => Err(RuntimeError::new(format!("Failed to read '{}': {}", path, err))),
+                    };
                 }
-                Box::new(clear)
+                Box::new(read_file)
             },
-        };
+        });
 
-        let round = NativeFunction {
-            name: "round".to_owned(),
-            arity: 1,
+        module.add_native(NativeFunction {
+            name: "write_file".to_owned(),
+            arity: 2,
             function: {
-                fn round(vals: &[Value]) -> Value {
-                    return match vals[0].clone() {
-                        Value::Number(num) => Value::Number(num.round()),
-                        _ => Value::Null
+                fn write_file(_: &mut NativeCtx, vals: &[Value]) -> Result<Value, RuntimeError> {
+                    let path = string_arg("write_file", vals, 0)?;
+                    return match std::fs::write(path, vals[1].to_string()) {
+                        Ok(()) => Ok(Value::Null),
+                        Err(err) => Err(RuntimeError::new(format!("Failed to write '{}': {}", path, err))),
                     };
                 }
-                Box::new(round)
+                Box::new(write_file)
+            },
+        });
+
+        module.add_native(NativeFunction {
+            name: "file_exists".to_owned(),
+            arity: 1,
+            function: {
+                fn file_exists(_: &mut NativeCtx, vals: &[Value]) -> Result<Value, RuntimeError> {
+                    let path = string_arg("file_exists", vals, 0)?;
+                    return Ok(Value::Bool(std::path::Path::new(path).is_file()));
+                }
+                Box::new(file_exists)
             },
+        });
+
+        return module;
+    }
+
+    /// Gets the argument at index as a string, or an error naming the native and the argument.
+    fn string_arg<'a>(native_name: &str, vals: &'a [Value], index: usize) -> Result<&'a str, RuntimeError> {
+        return match &vals[index] {
+            Value::String(string) => Ok(string.as_str()),
+            _ => Err(RuntimeError::new(format!("{} expects a string for argument {}.", native_name, index + 1))),
         };
+    }
 
-        compiler.add_native(time);
-        compiler.add_native(print);
-        compiler.add_native(random_range);
-        compiler.add_native(to_number);
-        compiler.add_native(to_string);
-        compiler.add_native(get_input);
-        compiler.add_native(clear);
-        compiler.add_native(round);
-    }
\ No newline at end of file
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc};
+
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use crate::{cancel::CancelToken, capabilities::Capabilities, clock::{Clock, SystemClock, VirtualClock}, compiler::Compiler, interpreter::{Builtins, Interpreter, RunStatus, RuntimeError, RuntimeErrorKind, add_builtin_natives}, io::CaptureIo, native_module::NativeModule, value::{HostObject, NativeFunction, Value}, vm::{NativeCtx, VM}};
+
+    fn test_builtins() -> Builtins {
+        return Builtins {
+            rng: Rc::new(RefCell::new(StdRng::seed_from_u64(0))),
+            clock: Rc::new(RefCell::new(Box::new(SystemClock::new()))),
+            io: Rc::new(RefCell::new(Box::new(CaptureIo::new()))),
+        };
+    }
+
+    fn call_native(name: &str, args: &[Value]) -> Value {
+        return try_call_native(name, args).expect("Native returned an error");
+    }
+
+    fn try_call_native(name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+        return try_call_native_with(&test_builtins(), name, args);
+    }
+
+    fn try_call_native_with(builtins: &Builtins, name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+        let mut compiler = Compiler::new("");
+        add_builtin_natives(&mut compiler, builtins, Capabilities::ALL, true);
+        let output = compiler.compile().expect("Failed to compile");
+        let (_, native) = output.host_globals.iter().find(|(_, global)| match global {
+            Value::NativeFunc(native) => native.name == name,
+            _ => false,
+        }).cloned().expect("Native not found");
+        let mut vm = VM::new(output, CancelToken::new());
+        return vm.call_function(&native, args, &mut ());
+    }
+
+    fn string(s: &str) -> Value {
+        return Value::String(Rc::new(s.to_owned()));
+    }
+
+    #[test]
+    fn len() {
+        assert_eq!(Value::Number(5.0), call_native("len", &[string("héllo")]));
+        assert_eq!(Value::Number(2.0), call_native("len", &[Value::Tuple(Rc::new(vec![Value::Null, Value::Null]))]));
+        assert_eq!(Value::Null, call_native("len", &[Value::Number(1.0)]));
+    }
+
+    fn tuple(values: Vec<Value>) -> Value {
+        return Value::Tuple(Rc::new(values));
+    }
+
+    #[test]
+    fn upper() {
+        assert_eq!(string("HÉLLO"), call_native("upper", &[string("héllo")]));
+        assert_eq!("upper expects a string for argument 1.", try_call_native("upper", &[Value::Number(1.0)]).unwrap_err().message);
+    }
+
+    #[test]
+    fn lower() {
+        assert_eq!(string("héllo"), call_native("lower", &[string("HÉLLO")]));
+        assert_eq!("lower expects a string for argument 1.", try_call_native("lower", &[Value::Null]).unwrap_err().message);
+    }
+
+    #[test]
+    fn trim() {
+        assert_eq!(string("hi there"), call_native("trim", &[string("  hi there \n")]));
+        assert_eq!("trim expects a string for argument 1.", try_call_native("trim", &[Value::Bool(true)]).unwrap_err().message);
+    }
+
+    #[test]
+    fn split() {
+        assert_eq!(tuple(vec![string("a"), string("b"), string("")]), call_native("split", &[string("a,b,"), string(",")]));
+        assert_eq!(tuple(vec![string("h"), string("é")]), call_native("split", &[string("hé"), string("")]));
+        assert_eq!("split expects a string for argument 2.", try_call_native("split", &[string("a,b"), Value::Null]).unwrap_err().message);
+    }
+
+    #[test]
+    fn join() {
+        let parts = tuple(vec![string("a"), Value::Number(1.0), Value::Bool(true)]);
+        assert_eq!(string("a-1-true"), call_native("join", &[parts, string("-")]));
+        assert_eq!("join expects a tuple for argument 1.", try_call_native("join", &[string("ab"), string("-")]).unwrap_err().message);
+    }
+
+    #[test]
+    fn replace() {
+        assert_eq!(string("hello gart"), call_native("replace", &[string("hello world"), string("world"), string("gart")]));
+        assert_eq!("replace expects a string for argument 2.", try_call_native("replace", &[string("hello"), Value::Null, string("")]).unwrap_err().message);
+    }
+
+    #[test]
+    fn find() {
+        assert_eq!(Value::Number(2.0), call_native("find", &[string("héllo"), string("llo")]));
+        assert_eq!(Value::Number(-1.0), call_native("find", &[string("hello"), string("z")]));
+        assert_eq!("find expects a string for argument 1.", try_call_native("find", &[Value::Null, string("z")]).unwrap_err().message);
+    }
+
+    #[test]
+    fn starts_with() {
+        assert_eq!(Value::Bool(true), call_native("starts_with", &[string("gart"), string("ga")]));
+        assert_eq!(Value::Bool(false), call_native("starts_with", &[string("gart"), string("rt")]));
+        assert_eq!("starts_with expects a string for argument 2.", try_call_native("starts_with", &[string("gart"), Value::Number(1.0)]).unwrap_err().message);
+    }
+
+    #[test]
+    fn ends_with() {
+        assert_eq!(Value::Bool(true), call_native("ends_with", &[string("gart"), string("rt")]));
+        assert_eq!(Value::Bool(false), call_native("ends_with", &[string("gart"), string("ga")]));
+        assert_eq!("ends_with expects a string for argument 1.", try_call_native("ends_with", &[Value::Null, string("ga")]).unwrap_err().message);
+    }
+
+    #[test]
+    fn repeat() {
+        assert_eq!(string("ababab"), call_native("repeat", &[string("ab"), Value::Number(3.0)]));
+        assert_eq!(string(""), call_native("repeat", &[string("ab"), Value::Number(0.0)]));
+        assert_eq!("repeat expects a non-negative integer for argument 2.", try_call_native("repeat", &[string("ab"), Value::Number(-1.0)]).unwrap_err().message);
+        assert_eq!("repeat expects a non-negative integer for argument 2.", try_call_native("repeat", &[string("ab"), Value::Number(1.5)]).unwrap_err().message);
+        assert_eq!("repeat expects a string for argument 1.", try_call_native("repeat", &[Value::Number(1.0), Value::Number(2.0)]).unwrap_err().message);
+        assert_eq!(string(""), call_native("repeat", &[string(""), Value::Number(1e300)]));
+        let err = try_call_native("repeat", &[string("ab"), Value::Number(1e300)]).unwrap_err();
+        assert_eq!("repeat result is too large.", err.message);
+        assert!(try_call_native("repeat", &[string("ab"), Value::Number(2f64.powi(62))]).is_err());
+    }
+
+    #[test]
+    fn format() {
+        let values = tuple(vec![string("gart"), Value::Number(3.0)]);
+        assert_eq!(string("gart scored 3!"), call_native("format", &[string("{} scored {}!"), values]));
+        assert_eq!(string("score: 10"), call_native("format", &[string("score: {}"), Value::Number(10.0)]));
+        let err = try_call_native("format", &[string("{} and {}"), Value::Number(10.0)]).unwrap_err();
+        assert_eq!("format expects a value for each {}, but got 1 for 2.", err.message);
+        assert_eq!("format expects a string for argument 1.", try_call_native("format", &[Value::Null, Value::Number(10.0)]).unwrap_err().message);
+    }
+
+    #[test]
+    fn unary_math() {
+        assert_eq!(Value::Number(1.0), call_native("floor", &[Value::Number(1.7)]));
+        assert_eq!(Value::Number(2.0), call_native("ceil", &[Value::Number(1.2)]));
+        assert_eq!(Value::Number(3.0), call_native("abs", &[Value::Number(-3.0)]));
+        assert_eq!(Value::Number(4.0), call_native("sqrt", &[Value::Number(16.0)]));
+        assert_eq!(Value::Number(0.0), call_native("sin", &[Value::Number(0.0)]));
+        assert_eq!(Value::Number(1.0), call_native("cos", &[Value::Number(0.0)]));
+        assert_eq!(Value::Number(0.0), call_native("tan", &[Value::Number(0.0)]));
+        assert_eq!(Value::Number(1.0), call_native("log", &[Value::Number(std::f64::consts::E)]));
+        assert_eq!(Value::Number(1.0), call_native("exp", &[Value::Number(0.0)]));
+    }
+
+    #[test]
+    fn binary_math() {
+        assert_eq!(Value::Number(8.0), call_native("pow", &[Value::Number(2.0), Value::Number(3.0)]));
+        assert_eq!(Value::Number(std::f64::consts::FRAC_PI_2), call_native("atan2", &[Value::Number(1.0), Value::Number(0.0)]));
+        assert_eq!(Value::Number(-1.0), call_native("min", &[Value::Number(-1.0), Value::Number(2.0)]));
+        assert_eq!(Value::Number(2.0), call_native("max", &[Value::Number(-1.0), Value::Number(2.0)]));
+    }
+
+    #[test]
+    fn clamp() {
+        assert_eq!(Value::Number(5.0), call_native("clamp", &[Value::Number(7.0), Value::Number(0.0), Value::Number(5.0)]));
+        assert_eq!(Value::Number(0.0), call_native("clamp", &[Value::Number(-7.0), Value::Number(0.0), Value::Number(5.0)]));
+        assert!(try_call_native("clamp", &[Value::Number(1.0), Value::Number(5.0), Value::Number(0.0)]).is_err());
+    }
+
+    #[test]
+    fn error_math_non_number() {
+        let err = try_call_native("sqrt", &[string("16")]).unwrap_err();
+        assert_eq!("sqrt expects number arguments.", err.message);
+        let err = try_call_native("pow", &[Value::Number(2.0), Value::Null]).unwrap_err();
+        assert_eq!("pow expects number arguments.", err.message);
+    }
+
+    #[test]
+    fn math_constants() {
+        let record = NativeFunction::with_data::<Vec<Value>>("record", 1, |ctx, vals| {
+            ctx.data.push(vals[0].clone());
+            return Ok(Value::Null);
+        });
+        let source = "record(math.PI)\nrecord(math.E)\nrecord(math.exp(1000))";
+        let mut interpreter = Interpreter::builder().data(vec![]).native(record).build(source.to_owned()).unwrap();
+        assert!(interpreter.run().is_ok());
+        let expected = vec![Value::Number(std::f64::consts::PI), Value::Number(std::f64::consts::E), Value::Number(f64::INFINITY)];
+        assert_eq!(&expected, interpreter.data());
+    }
+
+    fn random_sequence(builtins: &Builtins) -> Vec<Value> {
+        let mut sequence = vec![];
+        for _ in 0..5 {
+            sequence.push(try_call_native_with(builtins, "random", &[]).unwrap());
+            sequence.push(try_call_native_with(builtins, "random_int", &[Value::Number(1.0), Value::Number(100.0)]).unwrap());
+        }
+        return sequence;
+    }
+
+    #[test]
+    fn seeded_random_is_reproducible() {
+        let builtins = test_builtins();
+        *builtins.rng.borrow_mut() = StdRng::seed_from_u64(7);
+        let first = random_sequence(&builtins);
+        try_call_native_with(&builtins, "seed", &[Value::Number(7.0)]).unwrap();
+        let second = random_sequence(&builtins);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn random_ranges() {
+        for _ in 0..100 {
+            let Value::Number(num) = call_native("random", &[]) else { panic!("Expected number") };
+            assert!((0.0..1.0).contains(&num));
+            let Value::Number(int) = call_native("random_int", &[Value::Number(-2.0), Value::Number(2.0)]) else { panic!("Expected number") };
+            assert!((-2.0..=2.0).contains(&int) && int.fract() == 0.0);
+        }
+        assert!(try_call_native("random_int", &[Value::Number(2.0), Value::Number(1.0)]).is_err());
+        assert!(try_call_native("seed", &[Value::Number(-1.0)]).is_err());
+
+        let Value::Number(num) = call_native("random_range", &[Value::Number(-1.5), Value::Number(1.5)]) else { panic!("Expected number") };
+        assert!((-1.5..=1.5).contains(&num));
+        assert_eq!(Value::Null, call_native("random_range", &[Value::Number(2.0), Value::Number(1.0)]));
+        assert_eq!(Value::Null, call_native("random_range", &[Value::Number(f64::NEG_INFINITY), Value::Number(f64::INFINITY)]));
+        assert_eq!(Value::Null, call_native("random_range", &[Value::Number(0.0), Value::Number(f64::INFINITY)]));
+        assert_eq!(Value::Null, call_native("random_range", &[Value::Number(f64::NEG_INFINITY), Value::Number(0.0)]));
+        assert_eq!(Value::Null, call_native("random_range", &[Value::Number(f64::NAN), Value::Number(1.0)]));
+        assert_eq!(Value::Null, call_native("random_range", &[Value::Number(-f64::MAX), Value::Number(f64::MAX)]));
+    }
+
+    #[test]
+    fn choice() {
+        let values = tuple(vec![Value::Number(1.0), Value::Number(2.0)]);
+        let Value::Tuple(options) = values.clone() else { unreachable!() };
+        assert!(options.contains(&call_native("choice", &[values])));
+        assert_eq!(string("é"), call_native("choice", &[string("é")]));
+        assert!(try_call_native("choice", &[tuple(vec![])]).is_err());
+        assert!(try_call_native("choice", &[Value::Number(1.0)]).is_err());
+    }
+
+    #[test]
+    fn shuffle() {
+        let values: Vec<Value> = (0..10).map(|i| Value::Number(i as f64)).collect();
+        let Value::Tuple(shuffled) = call_native("shuffle", &[tuple(values.clone())]) else { panic!("Expected tuple") };
+        let mut sorted: Vec<f64> = shuffled.iter().map(|v| if let Value::Number(n) = v { *n } else { -1.0 }).collect();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!((0..10).map(|i| i as f64).collect::<Vec<f64>>(), sorted);
+        assert!(try_call_native("shuffle", &[string("abc")]).is_err());
+    }
+
+    #[test]
+    fn interpreter_set_seed() {
+        let mut interpreter = Interpreter::new("var x = random.random()".to_owned(), vec![]).unwrap();
+        interpreter.set_seed(3);
+        assert_eq!(StdRng::seed_from_u64(3), *interpreter.builtins.rng.borrow());
+    }
+
+    #[test]
+    fn virtual_clock_natives() {
+        let virtual_clock = VirtualClock::new(1000.0);
+        let builtins = test_builtins();
+        *builtins.clock.borrow_mut() = Box::new(virtual_clock.clone());
+
+        assert_eq!(Value::Number(1000.0), try_call_native_with(&builtins, "time", &[]).unwrap());
+        try_call_native_with(&builtins, "sleep", &[Value::Number(1.5)]).unwrap();
+        assert_eq!(Value::Number(1.5), try_call_native_with(&builtins, "monotonic", &[]).unwrap());
+        assert_eq!(Value::Number(1001.5), try_call_native_with(&builtins, "time", &[]).unwrap());
+        assert_eq!(1.5, virtual_clock.monotonic());
+        assert!(try_call_native_with(&builtins, "sleep", &[Value::Number(-1.0)]).is_err());
+        let err = try_call_native_with(&builtins, "sleep", &[Value::Number(10f64.powi(300))]).unwrap_err();
+        assert_eq!("sleep expects a non-negative number of seconds.", err.message);
+        assert_eq!(1.5, virtual_clock.monotonic());
+    }
+
+    #[test]
+    fn interpreter_set_clock() {
+        let virtual_clock = VirtualClock::new(0.0);
+        let mut interpreter = Interpreter::new("time.sleep(60)".to_owned(), vec![]).unwrap();
+        interpreter.set_clock(virtual_clock.clone());
+        assert!(interpreter.run().is_ok());
+        assert_eq!(60.0, virtual_clock.time());
+    }
+
+    #[test]
+    fn capture_io() {
+        let source = r#"
+import io
+clear()
+var name = input("Name: ")
+print("hello " + name)
+print((1, 2))"#;
+        let io = CaptureIo::with_inputs(["gart"]);
+        let mut interpreter = Interpreter::builder().io(io.clone()).build(source.to_owned()).unwrap();
+        assert!(interpreter.run().is_ok());
+
+        assert_eq!(vec!["Name: ", "hello gart", "(1, 2)"], io.output());
+        assert_eq!(1, io.clear_count());
+    }
+
+    #[test]
+    fn native_host_data() {
+        let add_score = NativeFunction::with_data::<f64>("add_score", 1, |ctx, vals| {
+            if let Value::Number(num) = vals[0] {
+                *ctx.data += num;
+            }
+            return Ok(Value::Number(*ctx.data));
+        });
+        let mut interpreter = Interpreter::builder().data(10.0).native(add_score).build("add_score(5)\nadd_score(2.5)".to_owned()).unwrap();
+        assert!(interpreter.run().is_ok());
+        assert_eq!(17.5, *interpreter.data());
+
+        let mut wrong_data = Interpreter::builder().native(NativeFunction::with_data::<f64>("add_score", 0, |_, _| Ok(Value::Null))).build("add_score()".to_owned()).unwrap();
+        assert!(wrong_data.run().is_err());
+    }
+
+    #[test]
+    fn native_calls_script_function() {
+        let call_twice = NativeFunction::with_data::<Vec<f64>>("call_twice", 2, |ctx, vals| {
+            let once = ctx.call(&vals[0], &[vals[1].clone()])?;
+            let twice = ctx.call(&vals[0], &[once])?;
+            if let Value::Number(num) = twice {
+                ctx.data.push(num);
+            }
+            return Ok(twice);
+        });
+        let source = r#"
+fn double(x):
+    return x * 2
+
+fn quadruple(x):
+    return call_twice(double, x)
+
+var result = call_twice(quadruple, 1) + 1"#;
+        let mut interpreter = Interpreter::builder().data(vec![]).native(call_twice).build(source.to_owned()).unwrap();
+        assert!(interpreter.run().is_ok());
+        assert_eq!(&vec![4.0, 16.0, 16.0], interpreter.data());
+
+        let failing = NativeFunction::with_data::<()>("call", 1, |ctx, vals| ctx.call(&vals[0], &[]));
+        let mut interpreter = Interpreter::builder().native(failing).build("fn fail():\n    return 1 + null\ncall(fail)".to_owned()).unwrap();
+        assert!(interpreter.run().is_err());
+    }
+
+    #[test]
+    fn native_callback_errors() {
+        let cancel_token = CancelToken::new();
+        let native_token = cancel_token.clone();
+        let cancel_and_call = NativeFunction::with_data::<()>("cancel_and_call", 1, move |ctx, vals| {
+            native_token.cancel();
+            return ctx.call(&vals[0], &[]);
+        });
+        let source = r#"
+fn spin():
+    var i = 0
+    while true:
+        i = i + 1
+cancel_and_call(spin)"#;
+        let mut interpreter = Interpreter::builder().cancel_token(cancel_token).native(cancel_and_call).build(source.to_owned()).unwrap();
+        let err = interpreter.run().unwrap_err();
+        assert_eq!(RuntimeErrorKind::Cancelled, err.kind);
+        assert!(err.trace[0].ends_with("in spin"));
+        assert!(err.trace[1].ends_with("in script"));
+
+        // a native that handles the error leaves the script running where it was
+        let try_call = NativeFunction::with_data::<()>("try_call", 1, |ctx, vals| {
+            return Ok(ctx.call(&vals[0], &[]).unwrap_or(Value::Null));
+        });
+        let source = r#"
+import io
+fn fail():
+    return 1 + null
+fn run():
+    var before = "kept"
+    var result = try_call(fail)
+    return before, result
+var before, result = run()
+print(before)
+print(result)"#;
+        let io = CaptureIo::new();
+        let mut interpreter = Interpreter::builder().io(io.clone()).native(try_call).build(source.to_owned()).unwrap();
+        assert!(interpreter.run().is_ok());
+        assert_eq!(vec!["kept", "NULL"], io.output());
+    }
+
+    struct Entity {
+        id: f64,
+    }
+
+    impl HostObject for Entity {
+        fn type_name(&self) -> &str {
+            return "Entity";
+        }
+    }
+
+    struct Sprite;
+
+    impl HostObject for Sprite {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "sprite")
+        }
+    }
+
+    #[test]
+    fn host_objects() {
+        let spawn = NativeFunction::with_data::<f64>("spawn", 0, |ctx, _| {
+            *ctx.data += 1.0;
+            return Ok(Value::host(Entity { id: *ctx.data }));
+        });
+        let entity_id = NativeFunction::with_data::<f64>("entity_id", 1, |_, vals| {
+            return match vals[0].downcast_host::<Entity>() {
+                Some(entity) => Ok(Value::Number(entity.id)),
+                None => Err(RuntimeError::new("entity_id expects an Entity.")),
+            };
+        });
+        let sprite = NativeFunction::with_data::<f64>("sprite", 0, |_, _| Ok(Value::host(Sprite)));
+        let source = r#"
+import io
+var a = spawn()
+var b = spawn()
+print(a == a)
+print(a == b)
+print(entity_id(b))
+print(a)
+print(sprite())"#;
+        let io = CaptureIo::new();
+        let mut interpreter = Interpreter::builder().data(0.0).io(io.clone()).natives(vec![spawn, entity_id, sprite]).build(source.to_owned()).unwrap();
+        assert!(interpreter.run().is_ok());
+        assert_eq!(vec!["true", "false", "2", "<Entity>", "sprite"], io.output());
+        assert_eq!(2.0, *interpreter.data());
+    }
+
+    struct Cell {
+        value: RefCell<Value>,
+    }
+
+    impl HostObject for Cell {
+        fn trace(&self, visit: &mut dyn FnMut(&Value)) {
+            visit(&self.value.borrow());
+        }
+
+        fn clear_references(&self) {
+            *self.value.borrow_mut() = Value::Null;
+        }
+    }
+
+    fn cell_interpreter(source: &str, io: CaptureIo) -> Interpreter {
+        return Interpreter::builder()
+            .io(io)
+            .register_fn("cell", || Rc::new(Cell { value: RefCell::new(Value::Null) }))
+            .register_fn("set", |cell: Rc<Cell>, value: Value| *cell.value.borrow_mut() = value)
+            .register_fn("get", |cell: Rc<Cell>| cell.value.borrow().clone())
+            .build(source.to_owned())
+            .unwrap();
+    }
+
+    #[test]
+    fn collect_cycles() {
+        let source = r#"
+import io
+var keep = cell()
+set(keep, (keep, "kept"))
+var i = 0
+var c = null
+while i < 1000:
+    c = cell()
+    set(c, (c, i))
+    i = i + 1
+print(get(keep)[1])
+print(get(c)[1])"#;
+        let io = CaptureIo::new();
+        let mut interpreter = cell_interpreter(source, io.clone());
+        assert!(interpreter.run().is_ok());
+        assert_eq!(vec!["kept", "999"], io.output());
+
+        let stats = interpreter.gc_stats();
+        assert!(stats.collections > 0);
+        assert!(stats.objects_collected > 0);
+        assert!(stats.tracked_objects < 1000);
+
+        interpreter.collect_garbage();
+        // keep and the last c, each with its tuple
+        assert_eq!(4, interpreter.gc_stats().tracked_objects);
+        assert_eq!(0, interpreter.collect_garbage());
+    }
+
+    #[test]
+    fn cycles_within_memory_limit() {
+        let source = r#"
+var i = 0
+while i < 5000:
+    var c = cell()
+    set(c, (c, "abcdefghijklmnopqrstuvwxyz"))
+    i = i + 1"#;
+        let mut interpreter = Interpreter::builder()
+            .memory_limit(1 << 14)
+            .register_fn("cell", || Rc::new(Cell { value: RefCell::new(Value::Null) }))
+            .register_fn("set", |cell: Rc<Cell>, value: Value| *cell.value.borrow_mut() = value)
+            .build(source.to_owned())
+            .unwrap();
+        assert!(interpreter.run().is_ok());
+    }
+
+    #[test]
+    fn gc_stress() {
+        let source = r#"
+import io
+fn build(n):
+    var c = cell()
+    set(c, (c, n, string(n) + "!"))
+    return c
+var cells = (build(1), build(2), build(3))
+var i = 0
+var total = 0
+while i < 50:
+    var c = build(i)
+    total = total + get(c)[1]
+    i = i + 1
+print(total)
+print(get(cells[1])[2])
+print(get(get(cells[2])[0])[1])"#;
+        let io = CaptureIo::new();
+        let mut interpreter = cell_interpreter(source, io.clone());
+        interpreter.vm.heap.stress = true;
+        assert!(interpreter.run().is_ok());
+        assert_eq!(vec!["1225", "2!", "3"], io.output());
+    }
+
+    #[test]
+    fn host_value_identity() {
+        let entity = Value::host(Entity { id: 1.0 });
+        assert_eq!(entity, entity.clone());
+        assert_ne!(entity, Value::host(Entity { id: 1.0 }));
+        assert_eq!(1.0, entity.host_rc::<Entity>().unwrap().id);
+        assert!(entity.downcast_host::<Sprite>().is_none());
+        assert!(Value::Null.downcast_host::<Entity>().is_none());
+    }
+
+    #[test]
+    fn register_fn() {
+        let source = r#"
+import io
+print(add(1, 2))
+print(greet("gart", null))
+print(greet("gart", "!"))
+print(sum((1, 2, 3)))
+print(range(3))"#;
+        let io = CaptureIo::new();
+        let mut interpreter = Interpreter::builder()
+            .io(io.clone())
+            .register_fn("add", |a: f64, b: f64| a + b)
+            .register_fn("greet", |name: String, suffix: Option<String>| format!("hello {}{}", name, suffix.unwrap_or_default()))
+            .register_fn("sum", |nums: Vec<f64>| nums.iter().sum::<f64>())
+            .register_fn("range", |n: f64| (0..n as usize).map(|i| i as f64).collect::<Vec<f64>>())
+            .build(source.to_owned())
+            .unwrap();
+        assert!(interpreter.run().is_ok());
+        assert_eq!(vec!["3", "hello gart", "hello gart!", "6", "(0, 1, 2)"], io.output());
+
+        let mut interpreter = Interpreter::builder().register_fn("add", |a: f64, b: f64| a + b).build("add(1, true)".to_owned()).unwrap();
+        assert_eq!("add expects a number for argument 2.", interpreter.run().unwrap_err().message);
+        let mut interpreter = Interpreter::builder().register_fn("add", |a: f64, b: f64| a + b).build("add(1)".to_owned()).unwrap();
+        assert_eq!("Expected 2 arguments, but got 1.", interpreter.run().unwrap_err().message);
+    }
+
+    #[gart::native]
+    fn hypot(a: f64, b: f64) -> f64 {
+        return (a * a + b * b).sqrt();
+    }
+
+    #[gart::native(name = "score")]
+    fn add_score(ctx: &mut NativeCtx<f64>, points: Option<f64>) -> f64 {
+        *ctx.data += points.unwrap_or(1.0);
+        return *ctx.data;
+    }
+
+    #[gart::native]
+    fn apply(ctx: &mut NativeCtx, func: Value, val: Value) -> Result<Value, RuntimeError> {
+        return ctx.call(&func, &[val]);
+    }
+
+    struct Text;
+
+    #[gart::module]
+    impl Text {
+        fn shout(text: String) -> String {
+            return text.to_uppercase() + "!";
+        }
+
+        #[native(name = "words")]
+        fn split_words(text: String) -> Vec<String> {
+            return text.split_whitespace().map(str::to_owned).collect();
+        }
+
+        fn log(_text: String) {}
+    }
+
+    #[test]
+    fn native_macros() {
+        let source = r#"
+import io
+fn double(x):
+    return x * 2
+
+print(hypot(3, 4))
+print(score(null))
+print(score(5))
+print(apply(double, 4))
+print(shout("hi"))
+print(words("a b  c"))
+print(log("quiet"))"#;
+        let io = CaptureIo::new();
+        let mut interpreter = Interpreter::builder()
+            .data(0.0)
+            .io(io.clone())
+            .natives(vec![hypot_native(), add_score_native(), apply_native()])
+            .natives(Text::natives())
+            .build(source.to_owned())
+            .unwrap();
+        assert!(interpreter.run().is_ok());
+        assert_eq!(vec!["5", "1", "6", "8", "HI!", "(a, b, c)", "NULL"], io.output());
+
+        let mut interpreter = Interpreter::builder().native(hypot_native()).build("hypot(1, \"2\")".to_owned()).unwrap();
+        assert_eq!("hypot expects a number for argument 2.", interpreter.run().unwrap_err().message);
+        let mut interpreter = Interpreter::builder().native(apply_native()).build("apply(1, 2)".to_owned()).unwrap();
+        assert_eq!("Can only call functions.", interpreter.run().unwrap_err().message);
+    }
+
+    #[test]
+    fn native_modules() {
+        let source = r#"
+import io
+print(math.sqrt(16))
+io.print(string.upper("qualified"))
+import time.time
+print(time())
+import game.spawn
+print(spawn())
+import game
+print(time())"#;
+        let mut game = NativeModule::new("game");
+        game.add_native(NativeFunction::from_fn("spawn", || "spawned"));
+        game.add_native(NativeFunction::from_fn("time", || 42.0));
+        let io = CaptureIo::new();
+        let mut interpreter = Interpreter::builder().io(io.clone()).module(game).build(source.to_owned()).unwrap();
+        interpreter.set_clock(VirtualClock::new(7.0));
+        assert!(interpreter.run().is_ok());
+        assert_eq!(vec!["4", "QUALIFIED", "7", "spawned", "42"], io.output());
+    }
+
+    #[test]
+    fn shadow_builtins() {
+        let source = r#"
+var max = 3
+fn find(x):
+    return x + max
+var E = 1
+print(find(E))
+print(math.max(max, 5))"#;
+        let io = CaptureIo::new();
+        let mut interpreter = Interpreter::builder().io(io.clone()).flat_builtins(true).build(source.to_owned()).unwrap();
+        assert!(interpreter.run().is_ok());
+        assert_eq!(vec!["4", "5"], io.output());
+
+        let errors = Interpreter::new("var max = 1\nvar max = 2".to_owned(), vec![]).err().unwrap();
+        assert_eq!("Aready a global variable with this name.", errors[0].message);
+        let errors = Interpreter::new("var max = 1\nimport math".to_owned(), vec![]).err().unwrap();
+        assert_eq!("Aready a global variable with this name.", errors[0].message);
+    }
+
+    #[test]
+    fn builtin_names() {
+        let errors = Interpreter::new("print(1)".to_owned(), vec![]).err().unwrap();
+        assert_eq!("'print' is in the io module, use io.print or import it.", errors[0].message);
+        let io = CaptureIo::new();
+        let mut interpreter = Interpreter::builder().io(io.clone()).flat_builtins(true).build("print(sqrt(16))".to_owned()).unwrap();
+        assert!(interpreter.run().is_ok());
+        assert_eq!(vec!["4"], io.output());
+
+        let errors = Interpreter::builder().register_fn("len", |_: Value| 0.0).module(NativeModule::new("math")).build("".to_owned()).err().unwrap();
+        assert_eq!("Host native 'len' has the same name as a built-in.", errors[0].message);
+        assert_eq!("Host module 'math' has the same name as a built-in module.", errors[1].message);
+        let errors = Interpreter::builder().flat_builtins(true).register_fn("sqrt", |x: f64| x).build("".to_owned()).err().unwrap();
+        assert_eq!("Host native 'sqrt' has the same name as a built-in.", errors[0].message);
+        assert!(Interpreter::builder().register_fn("sqrt", |x: f64| x).build("".to_owned()).is_ok());
+    }
+
+    #[test]
+    fn capability_profiles() {
+        let mut interpreter = Interpreter::builder().capabilities(Capabilities::pure()).build("var x = math.sqrt(string.upper(\"a\") == \"A\" and 4)".to_owned()).unwrap();
+        assert!(interpreter.run().is_ok());
+
+        let sources = [
+            ("input(\"name\")", "'input' needs the console capability."),
+            ("io.print(1)", "'io' needs the console capability."),
+            ("import random", "'random' needs the random capability."),
+            ("sleep(1)", "'sleep' needs the time capability."),
+        ];
+        for (source, message) in sources {
+            let errors = Interpreter::builder().capabilities(Capabilities::pure()).build(source.to_owned()).err().unwrap();
+            assert_eq!(message, errors[0].message, "Source: {}", source);
+        }
+
+        let errors = Interpreter::new("read_file(\"a\")".to_owned(), vec![]).err().unwrap();
+        assert_eq!("'read_file' needs the filesystem capability.", errors[0].message);
+        let input = NativeFunction::from_fn("input", |_: Value| "host input");
+        assert!(Interpreter::builder().capabilities(Capabilities::pure()).native(input).build("input(1)".to_owned()).is_ok());
+    }
+
+    #[test]
+    fn filesystem_natives() {
+        let path = std::env::temp_dir().join(format!("gart_fs_test_{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        assert_eq!(Value::Bool(false), call_native("file_exists", &[string(path)]));
+        assert!(try_call_native("read_file", &[string(path)]).is_err());
+        assert_eq!(Value::Null, call_native("write_file", &[string(path), string("hello")]));
+        assert_eq!(Value::Bool(true), call_native("file_exists", &[string(path)]));
+        assert_eq!(string("hello"), call_native("read_file", &[string(path)]));
+        assert!(try_call_native("read_file", &[Value::Null]).is_err());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn interpreter_cancel_from_native() {
+        let cancel_token = CancelToken::new();
+        let native_token = cancel_token.clone();
+        let stop = NativeFunction::from_fn("stop", move || native_token.cancel());
+        let mut interpreter = Interpreter::builder().cancel_token(cancel_token).native(stop).build("while true:\n    stop()".to_owned()).unwrap();
+
+        let err = interpreter.run().unwrap_err();
+        assert_eq!(RuntimeErrorKind::Cancelled, err.kind);
+        assert!(interpreter.cancel_token().is_cancelled());
+        assert_eq!(Ok(RunStatus::Finished), interpreter.run_with_budget(10));
+    }
+
+    #[test]
+    fn call_limits() {
+        let source = r#"
+fn count(n):
+    if n == 0:
+        return 0
+    return 1 + count(n - 1)
+var depth = count(50)"#;
+        assert!(Interpreter::builder().max_call_depth(100).build(source.to_owned()).unwrap().run().is_ok());
+
+        let err = Interpreter::builder().max_call_depth(20).build(source.to_owned()).unwrap().run().unwrap_err();
+        assert_eq!(RuntimeErrorKind::StackOverflow, err.kind);
+        assert_eq!("Stack overflow in 'count'.", err.message);
+
+        let err = Interpreter::builder().max_stack_size(40).build(source.to_owned()).unwrap().run().unwrap_err();
+        assert_eq!(RuntimeErrorKind::StackOverflow, err.kind);
+
+        // overflowing inside a native's call back into the script is still a stack overflow
+        let source = r#"
+fn count(n):
+    if n == 0:
+        return 0
+    return 1 + count(n - 1)
+var depth = call(count)"#;
+        let call = NativeFunction::with_data::<()>("call", 1, |ctx, vals| ctx.call(&vals[0], &[Value::Number(50.0)]));
+        let err = Interpreter::builder().max_call_depth(20).native(call).build(source.to_owned()).unwrap().run().unwrap_err();
+        assert_eq!(RuntimeErrorKind::StackOverflow, err.kind);
+        assert_eq!("Stack overflow in 'count'.", err.message);
+    }
+
+    #[test]
+    fn memory_limit() {
+        let source = r#"
+var s = "ab"
+while true:
+    s = s + s"#;
+        let err = Interpreter::builder().memory_limit(1 << 16).build(source.to_owned()).unwrap().run().unwrap_err();
+        assert_eq!(RuntimeErrorKind::OutOfMemory, err.kind);
+
+        let err = Interpreter::builder().memory_limit(1 << 16).build("var s = string.repeat(\"a\", 100000)".to_owned()).unwrap().run().unwrap_err();
+        assert_eq!(RuntimeErrorKind::OutOfMemory, err.kind);
+
+        // far more than could ever be allocated, so this only passes if the limit is checked first
+        let mut interpreter = Interpreter::builder().memory_limit(1 << 16).build("var s = string.repeat(\"ab\", math.pow(2, 49))".to_owned()).unwrap();
+        let err = interpreter.run().unwrap_err();
+        assert_eq!(RuntimeErrorKind::OutOfMemory, err.kind);
+        assert!(interpreter.gc_stats().bytes_used < 1 << 16);
+
+        // freed strings stop counting, so a loop that doesn't keep them can run forever
+        let source = r#"
+var i = 0
+var s = ""
+while i < 1000:
+    s = string(i) + "abcdefghijklmnopqrstuvwxyz"
+    i = i + 1"#;
+        let mut interpreter = Interpreter::builder().memory_limit(4096).build(source.to_owned()).unwrap();
+        assert!(interpreter.run().is_ok());
+        assert!(interpreter.memory_used() > 0);
+        assert!(interpreter.memory_used() < 4096);
+    }
+}
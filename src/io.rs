@@ -0,0 +1,80 @@
+use std::{cell::{Cell, RefCell}, collections::VecDeque, io::{self, Write}, rc::Rc};
+
+/// Destination for script output and source of script input. </br>
+/// The print, input and clear natives go through this, so hosts can redirect them.
+pub trait IoHandler {
+    /// Receives one printed line, without the trailing newline.
+    fn print(&mut self, line: &str);
+    /// Shows the prompt and returns the user's response.
+    fn input(&mut self, prompt: &str) -> String;
+    /// Clears all previous output.
+    fn clear(&mut self);
+}
+
+/// Writes to stdout and reads from stdin, clearing with ANSI escape codes.
+pub struct StdIo;
+
+impl IoHandler for StdIo {
+    fn print(&mut self, line: &str) {
+        println!("{}", line);
+    }
+
+    fn input(&mut self, prompt: &str) -> String {
+        print!("{}", prompt);
+        io::stdout().flush().unwrap();
+        let mut input = String::new();
+        io::stdin().read_line(&mut input).unwrap();
+        return input.trim().to_string();
+    }
+
+    fn clear(&mut self) {
+        print!("\x1B[2J\x1B[1;1H"); // basically regex so who the fuck knows
+    }
+}
+
+/// Keeps output in memory and answers input from a queue, for tests. </br>
+/// Clones share the same buffers, so the host can keep one to inspect after running.
+#[derive(Clone, Default)]
+pub struct CaptureIo {
+    output: Rc<RefCell<Vec<String>>>,
+    inputs: Rc<RefCell<VecDeque<String>>>,
+    clear_count: Rc<Cell<usize>>,
+}
+
+impl CaptureIo {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Creates a capture that answers each input() call with the next response. </br>
+    /// Once the responses run out, input() returns an empty string.
+    pub fn with_inputs<S: Into<String>>(responses: impl IntoIterator<Item = S>) -> Self {
+        let capture = Self::new();
+        capture.inputs.borrow_mut().extend(responses.into_iter().map(|response| response.into()));
+        return capture;
+    }
+
+    /// Every printed line and input prompt, in order.
+    pub fn output(&self) -> Vec<String> {
+        return self.output.borrow().clone();
+    }
+
+    pub fn clear_count(&self) -> usize {
+        return self.clear_count.get();
+    }
+}
+
+impl IoHandler for CaptureIo {
+    fn print(&mut self, line: &str) {
+        self.output.borrow_mut().push(line.to_owned());
+    }
+
+    fn input(&mut self, prompt: &str) -> String {
+        self.output.borrow_mut().push(prompt.to_owned());
+        return self.inputs.borrow_mut().pop_front().unwrap_or_default();
+    }
+
+    fn clear(&mut self) {
+        self.clear_count.set(self.clear_count.get() + 1);
+    }
+}
@@ -1,10 +1,25 @@
+// Lets the native macros refer to ::gart from inside this crate too.
+extern crate self as gart;
+
 pub(crate) mod scanner;
 pub(crate) mod token;
 pub(crate) mod opcode;
 pub(crate) mod chunk;
 pub(crate) mod compiler;
+pub(crate) mod optimizer;
+pub(crate) mod fold;
 pub(crate) mod value;
 pub(crate) mod parse;
 pub(crate) mod vm;
+pub(crate) mod convert;
+pub(crate) mod heap;
+pub(crate) mod interner;
+pub(crate) mod slot;
+pub(crate) mod native_module;
 pub mod interpreter;
-pub use {value::NativeFunction, value::Value};
\ No newline at end of file
+pub mod cancel;
+pub mod capabilities;
+pub mod clock;
+pub mod io;
+pub use {convert::FromValue, convert::IntoValue, convert::IntoNative, heap::GcStats, native_module::NativeModule, value::HostObject, value::NativeFunction, value::Value, vm::NativeCtx};
+pub use gart_macros::{module, native};
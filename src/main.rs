@@ -43,7 +43,12 @@ fn run_from_path(path: &str) {
     if let Ok(mut interpreter) = Interpreter::new(source, Vec::new()) {
         match interpreter.run() {
             Ok(_) => (),
-            Err(runtime_e) => println!("{:?}", runtime_e.message),
+            Err(runtime_e) => {
+                println!("Runtime error: {}", runtime_e.message);
+                for location in runtime_e.trace.iter() {
+                    println!("    {}", location);
+                }
+            },
         }    
     }
     else {
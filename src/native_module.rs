@@ -0,0 +1,54 @@
+use std::rc::Rc;
+
+use crate::value::{NativeFunction, Value};
+
+/// A named group of natives and constants. </br>
+/// Scripts reach members by qualified name (`math.sqrt`), or bring them into scope with `import math`.
+pub struct NativeModule {
+    pub name: String,
+    members: Vec<(String, Value)>,
+}
+
+impl NativeModule {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            members: vec![],
+        }
+    }
+
+    pub fn from_natives(name: &str, natives: Vec<NativeFunction>) -> Self {
+        let mut module = Self::new(name);
+        for native in natives.into_iter() {
+            module.add_native(native);
+        }
+        return module;
+    }
+
+    /// Adds a native, overwriting any member with the same name.
+    pub fn add_native(&mut self, native: NativeFunction) {
+        let name = native.name.to_owned();
+        self.add_member(name, Value::NativeFunc(Rc::new(native)));
+    }
+
+    /// Adds a constant, overwriting any member with the same name.
+    pub fn add_constant(&mut self, name: &str, value: Value) {
+        self.add_member(name.to_owned(), value);
+    }
+
+    fn add_member(&mut self, name: String, value: Value) {
+        match self.members.iter_mut().find(|(member_name, _)| *member_name == name) {
+            Some(member) => member.1 = value,
+            None => self.members.push((name, value)),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        return self.members.iter().find(|(member_name, _)| member_name == name).map(|(_, value)| value);
+    }
+
+    /// Members in the order they were added.
+    pub fn members(&self) -> &[(String, Value)] {
+        return &self.members;
+    }
+}
@@ -2,7 +2,7 @@ use std::fmt::Display;
 
 
 #[repr(u8)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum OpCode {
     Constant,
     Pop,
@@ -28,6 +28,26 @@ pub enum OpCode {
     True,
     False,
     Call,
+    BuildTuple,
+    Unpack,
+    Contains,
+    Index,
+    Slice,
+    NotEqual,
+    GreaterEqual,
+    LessEqual,
+    /// JumpIfFalse that also pops the condition, whichever way it goes.
+    PopJumpIfFalse,
+    /// Adds one to a local, for `i = i + 1`.
+    IncLocal,
+    /// Adds a constant to a local, for `x = x + k`.
+    AddLocalConst,
+    /// Jumps unless a local is less than a constant, for `while i < k:`.
+    LessLocalConstJump,
+    /// Call in tail position, the callee takes over the caller's frame instead of pushing a new one.
+    TailCall,
+    /// GetGlobal for a global the compiler knows is defined, so it skips the undefined check.
+    GetDefinedGlobal,
 }
 
 impl From::<OpCode> for u8 {
@@ -57,6 +77,20 @@ impl From::<OpCode> for u8 {
             OpCode::True => 22,
             OpCode::False => 23,
             OpCode::Call => 24,
+            OpCode::BuildTuple => 25,
+            OpCode::Unpack => 26,
+            OpCode::Contains => 27,
+            OpCode::Index => 28,
+            OpCode::Slice => 29,
+            OpCode::NotEqual => 30,
+            OpCode::GreaterEqual => 31,
+            OpCode::LessEqual => 32,
+            OpCode::PopJumpIfFalse => 33,
+            OpCode::IncLocal => 34,
+            OpCode::AddLocalConst => 35,
+            OpCode::LessLocalConstJump => 36,
+            OpCode::TailCall => 37,
+            OpCode::GetDefinedGlobal => 38,
         }
     }
 }
@@ -90,7 +124,48 @@ impl TryFrom::<u8> for OpCode {
             22 => Ok(OpCode::True),
             23 => Ok(OpCode::False),
             24 => Ok(OpCode::Call),
+            25 => Ok(OpCode::BuildTuple),
+            26 => Ok(OpCode::Unpack),
+            27 => Ok(OpCode::Contains),
+            28 => Ok(OpCode::Index),
+            29 => Ok(OpCode::Slice),
+            30 => Ok(OpCode::NotEqual),
+            31 => Ok(OpCode::GreaterEqual),
+            32 => Ok(OpCode::LessEqual),
+            33 => Ok(OpCode::PopJumpIfFalse),
+            34 => Ok(OpCode::IncLocal),
+            35 => Ok(OpCode::AddLocalConst),
+            36 => Ok(OpCode::LessLocalConstJump),
+            37 => Ok(OpCode::TailCall),
+            38 => Ok(OpCode::GetDefinedGlobal),
             _ => Err("Failed to convert u8 to OpCode")
         }
     }
-}
\ No newline at end of file
+}
+
+impl OpCode {
+    /// Number of operand bytes following the opcode.
+    pub fn operand_count(self) -> usize {
+        return match self {
+            OpCode::Constant
+            | OpCode::DefineGlobal
+            | OpCode::SetLocal
+            | OpCode::GetLocal
+            | OpCode::SetGlobal
+            | OpCode::GetGlobal
+            | OpCode::GetDefinedGlobal
+            | OpCode::Call
+            | OpCode::TailCall
+            | OpCode::BuildTuple
+            | OpCode::Unpack
+            | OpCode::IncLocal => 1,
+            OpCode::JumpIfFalse
+            | OpCode::Jump
+            | OpCode::JumpBack
+            | OpCode::PopJumpIfFalse
+            | OpCode::AddLocalConst => 2,
+            OpCode::LessLocalConstJump => 4,
+            _ => 0,
+        };
+    }
+}
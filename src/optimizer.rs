@@ -0,0 +1,337 @@
+use crate::{chunk::Chunk, opcode::OpCode, value::Value};
+
+/// Peephole pass over a finished chunk. </br>
+/// Fuses comparisons followed by Not into a single opcode, and JumpIfFalse followed by Pop into PopJumpIfFalse.
+/// Common updates and loop conditions on locals become superinstructions.
+/// Jumps landing on another Jump are pointed straight at its target.
+/// Instructions are decoded so jumps can be re-encoded once bytes are removed, lines stay with their instructions.
+pub(crate) fn optimize(chunk: &mut Chunk) {
+    let Some(mut instructions) = decode(chunk) else { return };
+    fuse_pairs(&mut instructions);
+    fuse_locals(&mut instructions, &chunk.constants);
+    thread_jumps(&mut instructions);
+    encode(chunk, &instructions);
+}
+
+struct Instruction {
+    op: OpCode,
+    /// Operand bytes, without the jump offset since the target is kept instead.
+    operands: Vec<u8>,
+    /// Index of the instruction a jump lands on.
+    target: Option<usize>,
+    line: usize,
+    removed: bool,
+}
+
+fn is_jump(op: OpCode) -> bool {
+    return matches!(op, OpCode::Jump | OpCode::JumpIfFalse | OpCode::PopJumpIfFalse | OpCode::JumpBack | OpCode::LessLocalConstJump);
+}
+
+fn is_forward_jump(op: OpCode) -> bool {
+    return is_jump(op) && op != OpCode::JumpBack;
+}
+
+/// Returns None if the chunk isn't well formed, in which case it's left alone.
+fn decode(chunk: &Chunk) -> Option<Vec<Instruction>> {
+    let mut instructions = vec![];
+    // instruction index starting at each byte offset
+    let mut starts = vec![None; chunk.bytes.len() + 1];
+    let mut jump_offsets = vec![];
+    let mut offset = 0;
+    while offset < chunk.bytes.len() {
+        let op = OpCode::try_from(chunk.bytes[offset]).ok()?;
+        let end = offset + 1 + op.operand_count();
+        let mut operands = chunk.bytes.get((offset + 1)..end)?.to_vec();
+        starts[offset] = Some(instructions.len());
+        if is_jump(op) {
+            // the jump offset is always the last two operand bytes
+            let low = operands.pop()? as usize;
+            let jump = ((operands.pop()? as usize) << 8) | low;
+            let target = match op {
+                OpCode::JumpBack => end.checked_sub(jump)?,
+                _ => end + jump,
+            };
+            jump_offsets.push((instructions.len(), target));
+        }
+        instructions.push(Instruction { op, operands, target: None, line: chunk.lines[offset], removed: false });
+        offset = end;
+    }
+    starts[offset] = Some(instructions.len());
+    for (index, target) in jump_offsets {
+        instructions[index].target = Some((*starts.get(target)?)?);
+    }
+    return Some(instructions);
+}
+
+/// Marks the instructions some jump lands on.
+fn jump_targets(instructions: &[Instruction]) -> Vec<bool> {
+    let mut is_target = vec![false; instructions.len() + 1];
+    for target in instructions.iter().filter(|instruction| instruction.removed == false).filter_map(|instruction| instruction.target) {
+        is_target[target] = true;
+    }
+    return is_target;
+}
+
+fn fuse_pairs(instructions: &mut [Instruction]) {
+    let is_target = jump_targets(instructions);
+
+    for index in 0..instructions.len().saturating_sub(1) {
+        // something jumps between the two, so the second has to stay on its own
+        if instructions[index].removed || is_target[index + 1] { continue; }
+        let fused = match (instructions[index].op, instructions[index + 1].op) {
+            (OpCode::Equal, OpCode::Not) => OpCode::NotEqual,
+            // not-less rather than greater-or-equal, so NaN compares the same as before
+            (OpCode::Less, OpCode::Not) => OpCode::GreaterEqual,
+            (OpCode::Greater, OpCode::Not) => OpCode::LessEqual,
+            (OpCode::JumpIfFalse, OpCode::Pop) => {
+                // the false branch has to start with its own Pop, which the fused jump skips
+                let target = instructions[index].target.unwrap();
+                if instructions.get(target).is_none_or(|landing| landing.op != OpCode::Pop) { continue; }
+                instructions[index].target = Some(target + 1);
+                OpCode::PopJumpIfFalse
+            },
+            _ => continue,
+        };
+        instructions[index].op = fused;
+        instructions[index + 1].removed = true;
+    }
+}
+
+/// Replaces updates and loop conditions on locals with superinstructions, saving a dispatch per replaced instruction.
+fn fuse_locals(instructions: &mut [Instruction], constants: &[Value]) {
+    let is_target = jump_targets(instructions);
+    let live: Vec<usize> = (0..instructions.len()).filter(|index| instructions[*index].removed == false).collect();
+
+    let mut position = 0;
+    while position < live.len() {
+        let window: Vec<usize> = live[position..].iter().take(5).copied().collect();
+        let ops: Vec<OpCode> = window.iter().map(|index| instructions[*index].op).collect();
+        // only the first instruction of a pattern can be landed on
+        let landed_inside = |count: usize| window[1..count].iter().any(|index| is_target[*index]);
+        let (op, operands, target, count) = match ops.as_slice() {
+            [OpCode::GetLocal, OpCode::Constant, OpCode::Add, OpCode::SetLocal, OpCode::Pop, ..]
+                if instructions[window[0]].operands == instructions[window[3]].operands && landed_inside(5) == false => {
+                let slot = instructions[window[0]].operands[0];
+                let constant = instructions[window[1]].operands[0];
+                match constants.get(constant as usize) {
+                    Some(Value::Number(num)) if *num == 1.0 => (OpCode::IncLocal, vec![slot], None, 5),
+                    _ => (OpCode::AddLocalConst, vec![slot, constant], None, 5),
+                }
+            },
+            [OpCode::GetLocal, OpCode::Constant, OpCode::Less, OpCode::PopJumpIfFalse, ..] if landed_inside(4) == false => {
+                let operands = vec![instructions[window[0]].operands[0], instructions[window[1]].operands[0]];
+                (OpCode::LessLocalConstJump, operands, instructions[window[3]].target, 4)
+            },
+            _ => {
+                position += 1;
+                continue;
+            },
+        };
+
+        let first = &mut instructions[window[0]];
+        first.op = op;
+        first.operands = operands;
+        first.target = target;
+        for index in window[1..count].iter() {
+            instructions[*index].removed = true;
+        }
+        position += count;
+    }
+}
+
+/// Threading adds jump distances together, so a chain is only followed while the jump still fits in its two bytes.
+fn thread_jumps(instructions: &mut [Instruction]) {
+    // nothing is removed from here on, so these are the offsets the chunk is encoded with
+    let offsets = offsets(instructions);
+    for index in 0..instructions.len() {
+        if instructions[index].removed || is_forward_jump(instructions[index].op) == false { continue; }
+        let mut target = instructions[index].target.unwrap();
+        // forward jumps only, so following them always ends
+        while let Some(landing) = instructions.get(target) {
+            if landing.op != OpCode::Jump || landing.removed { break; }
+            let next = landing.target.unwrap();
+            if offsets[next] - offsets[index + 1] > u16::MAX as usize { break; }
+            target = next;
+        }
+        instructions[index].target = Some(target);
+    }
+}
+
+/// Byte offset each instruction starts at once removed ones are left out, plus the end of the chunk.
+fn offsets(instructions: &[Instruction]) -> Vec<usize> {
+    let mut offsets = Vec::with_capacity(instructions.len() + 1);
+    let mut offset = 0;
+    for instruction in instructions.iter() {
+        offsets.push(offset);
+        if instruction.removed == false {
+            offset += 1 + instruction.op.operand_count();
+        }
+    }
+    offsets.push(offset);
+    return offsets;
+}
+
+fn encode(chunk: &mut Chunk, instructions: &[Instruction]) {
+    let offsets = offsets(instructions);
+
+    chunk.bytes.clear();
+    chunk.lines.clear();
+    for (index, instruction) in instructions.iter().enumerate() {
+        if instruction.removed { continue; }
+        chunk.write_op(instruction.op, instruction.line);
+        for byte in instruction.operands.iter() {
+            chunk.write_byte(*byte, instruction.line);
+        }
+        if let Some(target) = instruction.target {
+            let end = offsets[index + 1];
+            let jump = match instruction.op {
+                OpCode::JumpBack => end - offsets[target],
+                _ => offsets[target] - end,
+            };
+            assert!(jump <= u16::MAX as usize, "Optimized jump doesn't fit in 16 bits.");
+            chunk.write_byte(((jump >> 8) & 0xff) as u8, instruction.line);
+            chunk.write_byte((jump & 0xff) as u8, instruction.line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{chunk::Chunk, compiler::Compiler, opcode::OpCode, optimizer::optimize, value::Value};
+
+    fn chunk(bytes: Vec<u8>) -> Chunk {
+        let lines = (1..=bytes.len()).collect();
+        return Chunk { bytes, lines, constants: vec![] };
+    }
+
+    #[test]
+    fn fuse_comparisons() {
+        let mut optimized = chunk(vec![
+            OpCode::True.into(),
+            OpCode::True.into(),
+            OpCode::Equal.into(),
+            OpCode::Not.into(),
+            OpCode::Less.into(),
+            OpCode::Not.into(),
+            OpCode::Greater.into(),
+            OpCode::Not.into(),
+            OpCode::Return.into(),
+        ]);
+        optimize(&mut optimized);
+
+        let expected = Chunk {
+            bytes: vec![
+                OpCode::True.into(),
+                OpCode::True.into(),
+                OpCode::NotEqual.into(),
+                OpCode::GreaterEqual.into(),
+                OpCode::LessEqual.into(),
+                OpCode::Return.into(),
+            ],
+            lines: vec![1, 2, 3, 5, 7, 9],
+            constants: vec![],
+        };
+        assert_eq!(expected, optimized);
+    }
+
+    #[test]
+    fn keep_jump_targets() {
+        // !(a and b < c), the short circuit lands on the Not
+        let source = r#"var a = 1
+var b = 2
+var c = 3
+var d = !(a and b < c)"#;
+        let output = Compiler::new(source).compile().expect("Failed to compile");
+        let bytes = &output.script_function.chunk.bytes;
+        assert!(bytes.windows(2).any(|pair| pair == [OpCode::Less.into(), OpCode::Not.into()]));
+        assert!(bytes.contains(&OpCode::GreaterEqual.into()) == false);
+    }
+
+    #[test]
+    fn pop_jumps_and_threading() {
+        let source = r#"
+var x = 0
+if x:
+    if x:
+        x = 1
+else:
+    x = 2"#;
+        let output = Compiler::new(source).compile().expect("Failed to compile");
+
+        let expected_chunk = Chunk {
+            bytes: vec![
+                OpCode::Constant.into(), 0,
+                OpCode::DefineGlobal.into(), 0,
+                OpCode::GetGlobal.into(), 0,
+                OpCode::PopJumpIfFalse.into(), 0, 18,
+                // the inner if's jumps land on the outer if's jump over its else, so go straight to the end
+                OpCode::GetGlobal.into(), 0,
+                OpCode::PopJumpIfFalse.into(), 0, 18,
+                OpCode::Constant.into(), 1,
+                OpCode::SetGlobal.into(), 0,
+                OpCode::Pop.into(),
+                OpCode::Jump.into(), 0, 10,
+                OpCode::Pop.into(),
+                OpCode::Jump.into(), 0, 6,
+                OpCode::Pop.into(),
+                OpCode::Constant.into(), 2,
+                OpCode::SetGlobal.into(), 0,
+                OpCode::Pop.into(),
+                OpCode::Null.into(),
+                OpCode::Return.into(),
+            ],
+            lines: vec![2, 2, 2, 2, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 5, 5, 5, 5, 5, 6, 6, 6, 6, 6, 6, 6, 6, 7, 7, 7, 7, 7, 7, 7],
+            constants: vec![Value::Number(0.0), Value::Number(1.0), Value::Number(2.0)],
+        };
+        assert_eq!(expected_chunk, output.script_function.chunk);
+    }
+
+    #[test]
+    fn keep_long_jumps() {
+        // both jumps fit in 16 bits, but one straight to the end wouldn't
+        let skipped = 40000;
+        let mut bytes = vec![OpCode::Jump.into(), (skipped >> 8) as u8, (skipped & 0xff) as u8];
+        bytes.extend(std::iter::repeat_n(u8::from(OpCode::Pop), skipped));
+        bytes.extend([OpCode::Jump.into(), (skipped >> 8) as u8, (skipped & 0xff) as u8]);
+        bytes.extend(std::iter::repeat_n(u8::from(OpCode::Pop), skipped));
+        bytes.push(OpCode::Return.into());
+        let original = chunk(bytes);
+        let mut optimized = chunk(original.bytes.clone());
+        optimize(&mut optimized);
+
+        assert!(original.bytes == optimized.bytes, "Threading past 16 bits changed the jumps");
+    }
+
+    #[test]
+    fn fuse_locals() {
+        let source = r#"
+fn count():
+    var i = 0
+    var total = 0
+    while i < 10:
+        total = total + 2
+        i = i + 1
+    return total"#;
+        let output = Compiler::new(source).compile().expect("Failed to compile");
+        let Value::Func(function) = &output.script_function.chunk.constants[0] else { panic!("Expected a function constant") };
+
+        let expected_chunk = Chunk {
+            bytes: vec![
+                OpCode::Constant.into(), 0,
+                OpCode::Constant.into(), 1,
+                OpCode::LessLocalConstJump.into(), 1, 2, 0, 9,
+                OpCode::AddLocalConst.into(), 2, 3,
+                OpCode::IncLocal.into(), 1,
+                OpCode::JumpBack.into(), 0, 13,
+                OpCode::Pop.into(),
+                OpCode::GetLocal.into(), 2,
+                OpCode::Return.into(),
+                OpCode::Null.into(),
+                OpCode::Return.into(),
+            ],
+            lines: vec![3, 3, 4, 4, 5, 5, 5, 5, 5, 6, 6, 6, 7, 7, 8, 8, 8, 8, 8, 8, 8, 8, 8],
+            constants: vec![Value::Number(0.0), Value::Number(0.0), Value::Number(10.0), Value::Number(2.0), Value::Number(1.0)],
+        };
+        assert_eq!(expected_chunk, function.chunk);
+    }
+}
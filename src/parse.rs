@@ -78,6 +78,7 @@ pub enum ParseFn {
     Binary,
     Grouping,
     Call,
+    Index,
     Unary,
     Variable,
     String,
@@ -77,7 +77,12 @@ impl<'a> Scanner<'a> {
         match c {
             '(' => return self.make_token(TokenType::LeftParen),
             ')' => return self.make_token(TokenType::RightParen),
+            '[' => return self.make_token(TokenType::LeftBracket),
+            ']' => return self.make_token(TokenType::RightBracket),
             ',' => return self.make_token(TokenType::Comma),
+            // numbers have no members, so this is a trailing decimal point
+            '.' if self.previous_token == Some(TokenType::Number) => return self.make_err_token("Expect digit after '.'."),
+            '.' => return self.make_token(TokenType::Dot),
             '-' => return self.make_token(TokenType::Minus),
             '+' => return self.make_token(TokenType::Plus),
             '/' => return self.make_token(TokenType::Slash),
@@ -196,6 +201,9 @@ impl<'a> Scanner<'a> {
             "for" => TokenType::For,
             "fn" => TokenType::Fn,
             "if" => TokenType::If,
+            "import" => TokenType::Import,
+            "in" => TokenType::In,
+            "not" => TokenType::Not,
             "null" => TokenType::Null,
             "or" => TokenType::Or,
             "return" => TokenType::Return,
@@ -644,7 +652,7 @@ line 2"
 
     #[test]
     fn keywords() {
-        let source = r#"and else false for fn if null or return true var while"#;
+        let source = r#"and else false for fn if null or return true var while in not import"#;
         let mut scanner = Scanner::new(&source);
 
         let expected_tokens = vec![
@@ -660,8 +668,11 @@ line 2"
             Token::new(TokenType::True, 40, 4, 1),
             Token::new(TokenType::Var, 45, 3, 1),
             Token::new(TokenType::While, 49, 5, 1),
-            Token::new(TokenType::NewLine, 54, 0, 1),
-            Token::new(TokenType::Eof, 54, 0, 1),
+            Token::new(TokenType::In, 55, 2, 1),
+            Token::new(TokenType::Not, 58, 3, 1),
+            Token::new(TokenType::Import, 62, 6, 1),
+            Token::new(TokenType::NewLine, 68, 0, 1),
+            Token::new(TokenType::Eof, 68, 0, 1),
         ];
 
         for (i, expected_token) in expected_tokens.iter().enumerate() {
@@ -728,7 +739,7 @@ line 2"
 
     #[test]
     fn delimiter_tokens() {
-        let source = r#": , ( )"#;
+        let source = r#": , ( ) [ ] ."#;
         let mut scanner = Scanner::new(&source);
 
         let expected_tokens = vec![
@@ -736,8 +747,11 @@ line 2"
             Token::new(TokenType::Comma, 2, 1, 1),
             Token::new(TokenType::LeftParen, 4, 1, 1),
             Token::new(TokenType::RightParen, 6, 1, 1),
-            Token::new(TokenType::NewLine, 7, 0, 1),
-            Token::new(TokenType::Eof, 7, 0, 1),
+            Token::new(TokenType::LeftBracket, 8, 1, 1),
+            Token::new(TokenType::RightBracket, 10, 1, 1),
+            Token::new(TokenType::Dot, 12, 1, 1),
+            Token::new(TokenType::NewLine, 13, 0, 1),
+            Token::new(TokenType::Eof, 13, 0, 1),
         ];
 
         for (i, expected_token) in expected_tokens.iter().enumerate() {
@@ -0,0 +1,270 @@
+#[cfg(not(feature = "nan-boxing"))]
+pub(crate) use enum_slots::{Slot, Slots};
+#[cfg(feature = "nan-boxing")]
+pub(crate) use nan_boxed::{Slot, Slots};
+
+/// How values are stored on the vm's stack by default, a slot is just a Value.
+#[cfg(not(feature = "nan-boxing"))]
+mod enum_slots {
+    use crate::value::Value;
+
+    pub(crate) type Slot = Value;
+
+    /// Slots are plain values, so there's nothing to keep track of.
+    pub(crate) struct Slots;
+
+    impl Slots {
+        pub fn new() -> Self {
+            Self
+        }
+
+        pub fn store(&mut self, value: Value) -> Slot {
+            return value;
+        }
+
+        pub fn take(&self, slot: Slot) -> Value {
+            return slot;
+        }
+
+        pub fn value(&self, slot: &Slot) -> Value {
+            return slot.clone();
+        }
+
+        pub fn number(slot: &Slot) -> Option<f64> {
+            return match slot {
+                Value::Number(num) => Some(*num),
+                _ => None,
+            };
+        }
+
+        pub fn from_number(num: f64) -> Slot {
+            return Value::Number(num);
+        }
+
+        pub fn from_bool(b: bool) -> Slot {
+            return Value::Bool(b);
+        }
+
+        pub fn null() -> Slot {
+            return Value::Null;
+        }
+
+        pub fn is_falsey(&self, slot: &Slot) -> bool {
+            return *slot == Value::Null || *slot == Value::Bool(false);
+        }
+
+        pub fn equal(&self, a: &Slot, b: &Slot) -> bool {
+            return a == b;
+        }
+
+        pub fn should_collect(&self) -> bool {
+            return false;
+        }
+
+        pub fn collect(&mut self, _stack: &[Slot]) {}
+    }
+}
+
+/// How values are stored on the vm's stack with the `nan-boxing` feature. </br>
+/// Slots are 64 bit words, cheap to copy and never touching reference counts.
+/// Both representations have the same Slots api, so the vm works the same either way.
+#[cfg(feature = "nan-boxing")]
+mod nan_boxed {
+    use crate::value::Value;
+
+    /// Quiet NaN with one extra bit set, no arithmetic result has these bits so everything else is a number.
+    const QNAN: u64 = 0x7ffc_0000_0000_0000;
+    const SIGN: u64 = 0x8000_0000_0000_0000;
+    const NULL: u64 = QNAN | 1;
+    const FALSE: u64 = QNAN | 2;
+    const TRUE: u64 = QNAN | 3;
+    /// Handles have the sign bit set as well, with the index into the handle table in the low bits.
+    const HANDLE: u64 = SIGN | QNAN;
+
+    const MIN_COLLECT_AT: usize = 1024;
+
+    /// A number, bool or null stored directly, or a handle to a heap value in Slots. </br>
+    /// Not Copy on purpose, so the vm clones slots the same way in both representations.
+    #[derive(Clone, PartialEq, Debug)]
+    pub(crate) struct Slot(u64);
+
+    impl Slot {
+        fn is_number(&self) -> bool {
+            return self.0 & QNAN != QNAN;
+        }
+
+        fn handle(&self) -> Option<usize> {
+            if self.0 & HANDLE != HANDLE { return None; }
+            return Some((self.0 & !HANDLE) as usize);
+        }
+    }
+
+    /// Handle table holding the heap values slots point to. </br>
+    /// Handles only live on the stack, so entries no slot on the stack points to are freed by collect.
+    pub(crate) struct Slots {
+        objects: Vec<Option<Value>>,
+        free: Vec<usize>,
+        /// Entries in use, including ones no slot points to anymore.
+        live: usize,
+        collect_at: usize,
+    }
+
+    impl Slots {
+        pub fn new() -> Self {
+            Self {
+                objects: vec![],
+                free: vec![],
+                live: 0,
+                collect_at: MIN_COLLECT_AT,
+            }
+        }
+
+        pub fn store(&mut self, value: Value) -> Slot {
+            match value {
+                Value::Number(num) => return Self::from_number(num),
+                Value::Bool(b) => return Self::from_bool(b),
+                Value::Null => return Self::null(),
+                _ => (),
+            }
+            let index = match self.free.pop() {
+                Some(index) => {
+                    self.objects[index] = Some(value);
+                    index
+                },
+                None => {
+                    self.objects.push(Some(value));
+                    self.objects.len() - 1
+                },
+            };
+            self.live += 1;
+            return Slot(HANDLE | index as u64);
+        }
+
+        pub fn take(&self, slot: Slot) -> Value {
+            return self.value(&slot);
+        }
+
+        pub fn value(&self, slot: &Slot) -> Value {
+            if slot.is_number() {
+                return Value::Number(f64::from_bits(slot.0));
+            }
+            if let Some(index) = slot.handle() {
+                return self.objects[index].clone().expect("Slot points to a freed handle.");
+            }
+            return match slot.0 {
+                TRUE => Value::Bool(true),
+                FALSE => Value::Bool(false),
+                _ => Value::Null,
+            };
+        }
+
+        pub fn number(slot: &Slot) -> Option<f64> {
+            if slot.is_number() == false { return None; }
+            return Some(f64::from_bits(slot.0));
+        }
+
+        pub fn from_number(num: f64) -> Slot {
+            // NaNs with our tag bits set would read back as something else
+            if num.is_nan() { return Slot(f64::NAN.to_bits()); }
+            return Slot(num.to_bits());
+        }
+
+        pub fn from_bool(b: bool) -> Slot {
+            return Slot(if b { TRUE } else { FALSE });
+        }
+
+        pub fn null() -> Slot {
+            return Slot(NULL);
+        }
+
+        pub fn is_falsey(&self, slot: &Slot) -> bool {
+            return slot.0 == NULL || slot.0 == FALSE;
+        }
+
+        pub fn equal(&self, a: &Slot, b: &Slot) -> bool {
+            if a.handle().is_some() || b.handle().is_some() {
+                return self.value(a) == self.value(b);
+            }
+            if let (Some(num_a), Some(num_b)) = (Self::number(a), Self::number(b)) {
+                return num_a == num_b;
+            }
+            return a.0 == b.0;
+        }
+
+        pub fn should_collect(&self) -> bool {
+            return self.live >= self.collect_at;
+        }
+
+        /// Frees every handle no slot in the stack points to.
+        pub fn collect(&mut self, stack: &[Slot]) {
+            let mut marked = vec![false; self.objects.len()];
+            for index in stack.iter().filter_map(Slot::handle) {
+                marked[index] = true;
+            }
+            self.free.clear();
+            self.live = 0;
+            for (index, object) in self.objects.iter_mut().enumerate() {
+                if marked[index] {
+                    self.live += 1;
+                    continue;
+                }
+                *object = None;
+                self.free.push(index);
+            }
+            self.collect_at = (self.live * 2).max(MIN_COLLECT_AT);
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use std::rc::Rc;
+
+        use crate::{slot::Slots, value::Value};
+
+        #[test]
+        fn round_trip() {
+            let mut slots = Slots::new();
+            let values = [
+                Value::Number(1.5),
+                Value::Number(-0.0),
+                Value::Number(f64::INFINITY),
+                Value::Bool(true),
+                Value::Bool(false),
+                Value::Null,
+                Value::String(Rc::new("hi".to_owned())),
+                Value::Tuple(Rc::new(vec![Value::Null])),
+            ];
+            for value in values {
+                let slot = slots.store(value.clone());
+                assert_eq!(value, slots.value(&slot));
+            }
+
+            let nan = slots.store(Value::Number(f64::from_bits(0x7fff_ffff_ffff_ffff)));
+            assert!(Slots::number(&nan).is_some_and(f64::is_nan));
+            assert!(slots.equal(&nan, &nan) == false);
+            assert!(slots.is_falsey(&Slots::null()));
+            assert!(slots.is_falsey(&Slots::from_number(0.0)) == false);
+        }
+
+        #[test]
+        fn collect() {
+            let mut slots = Slots::new();
+            let kept = slots.store(Value::String(Rc::new("kept".to_owned())));
+            let string = Rc::new("dropped".to_owned());
+            slots.store(Value::String(string.clone()));
+            assert_eq!(2, Rc::strong_count(&string));
+
+            slots.collect(&[kept.clone(), Slots::from_number(1.0)]);
+            assert_eq!(1, Rc::strong_count(&string));
+            assert_eq!(Value::String(Rc::new("kept".to_owned())), slots.value(&kept));
+
+            // freed entries get reused
+            slots.store(Value::Null);
+            let reused = slots.store(Value::String(string));
+            assert!(slots.free.is_empty());
+            assert_eq!(2, slots.objects.len());
+            let same = slots.store(Value::String(Rc::new("dropped".to_owned())));
+            assert!(slots.equal(&reused, &same));
+        }
+    }
+}
@@ -24,10 +24,13 @@ impl Token {
 pub enum TokenType {
     LeftParen,
     RightParen,
+    LeftBracket,
+    RightBracket,
     Indent,
     Dedent,
     NewLine,
     Comma,
+    Dot,
     Minus,
     Plus,
     Colon,
@@ -50,6 +53,9 @@ pub enum TokenType {
     For,
     Fn,
     If,
+    Import,
+    In,
+    Not,
     Null,
     Or,
     Return,
@@ -1,7 +1,7 @@
-use std::{fmt::Display, rc::Rc};
+use std::{any::Any, fmt::Display, rc::Rc};
 use std::fmt::Debug;
 
-use crate::chunk::Chunk;
+use crate::{chunk::Chunk, interpreter::RuntimeError, vm::NativeCtx};
 
  #[derive(Clone, PartialEq, Debug)]
  pub enum Value {
@@ -11,7 +11,64 @@ use crate::chunk::Chunk;
     String(Rc<String>),
     Func(Rc<Function>),
     NativeFunc(Rc<NativeFunction>),
+    Tuple(Rc<Vec<Value>>),
+    Host(Rc<dyn HostObject>),
  }
+
+/// A Rust object handed to scripts as an opaque value. </br>
+/// Scripts can store and pass it around, and two host values are only equal if they are the same object.
+pub trait HostObject: Any {
+    /// Name used when the object is printed.
+    fn type_name(&self) -> &str {
+        return std::any::type_name::<Self>();
+    }
+
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<{}>", self.type_name())
+    }
+
+    /// Visits every value this object holds. </br>
+    /// Objects holding values should implement this, so the cycle collector can find cycles running through them.
+    fn trace(&self, _visit: &mut dyn FnMut(&Value)) {}
+
+    /// Drops every value this object holds. </br>
+    /// Called by the cycle collector once the object is only kept alive by a cycle, to break it.
+    fn clear_references(&self) {}
+}
+
+impl PartialEq for dyn HostObject {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(self, other)
+    }
+}
+
+impl Debug for dyn HostObject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HostObject").field("type_name", &self.type_name()).finish()
+    }
+}
+
+impl Value {
+    pub fn host(object: impl HostObject) -> Self {
+        return Value::Host(Rc::new(object));
+    }
+
+    /// Borrows the host object as T, if this is a host value holding a T.
+    pub fn downcast_host<T: HostObject>(&self) -> Option<&T> {
+        return match self {
+            Value::Host(object) => (object.as_ref() as &dyn Any).downcast_ref::<T>(),
+            _ => None,
+        };
+    }
+
+    /// Gets a shared handle to the host object as T, if this is a host value holding a T.
+    pub fn host_rc<T: HostObject>(&self) -> Option<Rc<T>> {
+        return match self {
+            Value::Host(object) => (object.clone() as Rc<dyn Any>).downcast::<T>().ok(),
+            _ => None,
+        };
+    }
+}
  
  #[derive(Clone, PartialEq, Debug)]
  pub struct Function {
@@ -23,7 +80,25 @@ use crate::chunk::Chunk;
 pub struct NativeFunction {
     pub name: String,
     pub arity: u8,
-    pub function: Box<dyn Fn(&[Value]) -> Value>,
+    pub function: Box<dyn Fn(&mut NativeCtx, &[Value]) -> Result<Value, RuntimeError>>,
+}
+
+impl NativeFunction {
+    /// Creates a native that works with the interpreter's host data of type T. </br>
+    /// Calling it from an interpreter holding a different data type is a runtime error.
+    pub fn with_data<T: 'static>(name: &str, arity: u8, function: impl Fn(&mut NativeCtx<T>, &[Value]) -> Result<Value, RuntimeError> + 'static) -> Self {
+        let native_name = name.to_owned();
+        return Self {
+            name: name.to_owned(),
+            arity,
+            function: Box::new(move |ctx, vals| {
+                let Some(mut ctx) = ctx.downcast::<T>() else {
+                    return Err(RuntimeError::new(format!("{} was called with the wrong type of host data.", native_name)));
+                };
+                return function(&mut ctx, vals);
+            }),
+        };
+    }
 }
 
 impl PartialEq for NativeFunction {
@@ -50,6 +125,15 @@ impl Debug for NativeFunction {
             Value::String(v) => write!(f, "{}", v),
             Value::Func(function) => write!(f, "fn {}", function.name),
             Value::NativeFunc(native_function) => write!(f, "fn {}", native_function.name),
+            Value::Tuple(values) => {
+                write!(f, "(")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 { write!(f, ", ")?; }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, ")")
+            },
+            Value::Host(object) => HostObject::fmt(object.as_ref(), f),
         }
     }
  }
\ No newline at end of file
@@ -1,12 +1,69 @@
-use std::{rc::Rc, time::{SystemTime, UNIX_EPOCH}};
+use std::{any::Any, rc::Rc, time::{SystemTime, UNIX_EPOCH}};
 
-use crate::{chunk::Chunk, compiler::{Compiler, CompilerOutput}, interpreter::RuntimeError, opcode::OpCode, value::{Function, NativeFunction, Value}};
+use crate::{cancel::CancelToken, chunk::Chunk, compiler::{Compiler, CompilerOutput}, heap::Heap, interner::Interner, interpreter::{RunStatus, RuntimeError, RuntimeErrorKind}, opcode::OpCode, slot::{Slot, Slots}, value::{Function, NativeFunction, Value}};
 
 
+/// Default for the most call frames a script can have at once.
+pub const DEFAULT_MAX_FRAMES: usize = 1024;
+/// Default for the most values the stack can hold.
+pub const DEFAULT_MAX_STACK: usize = 65536;
+
 pub struct VM {
-    pub stack: Vec<Value>,
-    pub globals: Vec<Option<Value>>,
-    call_frames: Vec<CallFrame>
+    pub(crate) stack: Vec<Slot>,
+    /// Globals that haven't been defined yet hold null, defined says which ones have.
+    pub globals: Vec<Value>,
+    defined: Vec<bool>,
+    call_frames: Vec<CallFrame>,
+    pub cancel_token: CancelToken,
+    /// Calls past this many frames fail with a stack overflow.
+    pub max_frames: usize,
+    /// Calls that would start with more values than this on the stack fail with a stack overflow.
+    pub max_stack: usize,
+    pub(crate) heap: Heap,
+    /// Strings from the compiler, which strings made at runtime reuse when their contents match.
+    pub(crate) interner: Interner,
+    /// Turns values into stack slots and back.
+    slots: Slots,
+    /// Natives currently running, errors only end the script once none are left to handle them.
+    native_depth: usize,
+}
+
+/// Passed to natives when they are called. </br>
+/// Gives access to the host data owned by the interpreter, and lets natives call back into script functions.
+pub struct NativeCtx<'a, T: ?Sized = dyn Any> {
+    vm: &'a mut VM,
+    pub data: &'a mut T,
+}
+
+impl<'a> NativeCtx<'a> {
+    /// Views the host data as T. </br>
+    /// Returns None if the interpreter was built with a different data type.
+    pub fn downcast<T: 'static>(&mut self) -> Option<NativeCtx<'_, T>> {
+        let data = self.data.downcast_mut::<T>()?;
+        return Some(NativeCtx { vm: self.vm, data });
+    }
+
+    /// Calls a function value with the arguments and returns its result. </br>
+    /// Script functions are run to completion before this returns.
+    pub fn call(&mut self, callee: &Value, args: &[Value]) -> Result<Value, RuntimeError> {
+        return self.vm.call_function(callee, args, self.data);
+    }
+}
+
+impl<'a, T: ?Sized> NativeCtx<'a, T> {
+    /// Checks a native can allocate bytes more without going over the interpreter's memory limit. </br>
+    /// Natives building large strings or tuples call this first, so the limit stops them before they allocate.
+    pub fn reserve(&mut self, bytes: usize) -> Result<(), RuntimeError> {
+        return self.vm.reserve(bytes);
+    }
+}
+
+impl<'a, T: 'static> NativeCtx<'a, T> {
+    /// Calls a function value with the arguments and returns its result. </br>
+    /// Script functions are run to completion before this returns.
+    pub fn call(&mut self, callee: &Value, args: &[Value]) -> Result<Value, RuntimeError> {
+        return self.vm.call_function(callee, args, self.data);
+    }
 }
 
 struct CallFrame {
@@ -14,26 +71,41 @@ struct CallFrame {
     /// Index in the stack where this call-frame starts.
     stack_offset: usize,
     pc: usize,
+    /// Frames this one replaced through tail calls, so traces can show they were there.
+    tail_calls: usize,
 }
 
 impl VM {
-    pub fn new(compiler_output: CompilerOutput) -> Self {
-        let mut globals = vec![None; compiler_output.globals_count];
-        for (i, native) in compiler_output.natives.into_iter().enumerate() {
-            globals[i] = Some(Value::NativeFunc(Rc::new(native)));            
+    pub fn new(compiler_output: CompilerOutput, cancel_token: CancelToken) -> Self {
+        let mut globals = vec![Value::Null; compiler_output.globals_count];
+        let mut defined = vec![false; compiler_output.globals_count];
+        for (index, host_global) in compiler_output.host_globals.into_iter() {
+            globals[index as usize] = host_global;
+            defined[index as usize] = true;
         }
         let mut vm = Self {
             stack: Vec::new(),
             globals: globals,
-            call_frames: vec![]
+            defined,
+            call_frames: vec![],
+            cancel_token,
+            max_frames: DEFAULT_MAX_FRAMES,
+            max_stack: DEFAULT_MAX_STACK,
+            heap: Heap::new(),
+            interner: compiler_output.interner,
+            slots: Slots::new(),
+            native_depth: 0,
         };
         let script_func = Rc::new(compiler_output.script_function);
-        vm.stack.push(Value::Func(script_func.clone()));
-        vm.call_value(Value::Func(script_func), 0);
+        vm.push(Value::Func(script_func.clone()));
+        vm.call_value(Value::Func(script_func), 0, false, &mut ()).expect("Script function takes no arguments.");
         return vm;
     }
 
-    pub fn step(&mut self) -> Result<bool, RuntimeError> {
+    /// Data is the host data handed to natives through their NativeCtx.
+    pub fn step(&mut self, data: &mut dyn Any) -> Result<bool, RuntimeError> {
+        if self.call_frames.len() == 0 { return Ok(false); }
+        if self.slots.should_collect() { self.slots.collect(&self.stack); }
         let operation = OpCode::try_from(self.read_byte());
         if operation.is_err() { 
             let err = self.runtime_error("Failed to convert byte to opcode");
@@ -42,40 +114,43 @@ impl VM {
         match operation.unwrap() {
             OpCode::Constant => {
                 let val = self.read_constant();
-                self.stack.push(val);
+                self.push(val);
             },
             OpCode::Pop => { self.stack.pop(); },
             OpCode::Equal => {
                 let b = self.stack.pop().unwrap();
                 let a = self.stack.pop().unwrap();
-                self.stack.push(Value::Bool(a == b));
+                self.stack.push(Slots::from_bool(self.slots.equal(&a, &b)));
+            },
+            OpCode::NotEqual => {
+                let b = self.stack.pop().unwrap();
+                let a = self.stack.pop().unwrap();
+                self.stack.push(Slots::from_bool(self.slots.equal(&a, &b) == false));
             },
             OpCode::Not => {
                 let val = self.stack.pop().unwrap();
-                let not_val = self.is_falsey(&val);
-                self.stack.push(Value::Bool(not_val));
+                let not_val = self.slots.is_falsey(&val);
+                self.stack.push(Slots::from_bool(not_val));
             },
-            OpCode::Greater => { if let Err(e) = self.binary_number_op(|a, b| Value::Bool(a > b)) { return Err(e); } },
-            OpCode::Less => { if let Err(e) = self.binary_number_op(|a, b| Value::Bool(a < b)) { return Err(e); } },
-            OpCode::Add => {
-                if matches!(&self.stack[self.stack.len() - 1], Value::String(_)) && matches!(&self.stack[self.stack.len() - 2], Value::String(_)) {
-                    if let Err(e) = self.concatenate() { return Err(e); }
-                }
-                else if let Err(e) = self.binary_number_op(|a, b| Value::Number(a + b)) { return Err(e); }
-             },
-            OpCode::Subtract => { if let Err(e) = self.binary_number_op(|a, b| Value::Number(a - b)) { return Err(e); } },
-            OpCode::Multiply => { if let Err(e) = self.binary_number_op(|a, b| Value::Number(a * b)) { return Err(e); } },
-            OpCode::Divide => { if let Err(e) = self.binary_number_op(|a, b| Value::Number(a / b)) { return Err(e); } },
+            OpCode::Greater => { if let Err(e) = self.binary_number_op(|a, b| Slots::from_bool(a > b)) { return Err(e); } },
+            OpCode::Less => { if let Err(e) = self.binary_number_op(|a, b| Slots::from_bool(a < b)) { return Err(e); } },
+            // written as the negated comparison they replace, so NaN compares the same as before
+            OpCode::GreaterEqual => { if let Err(e) = self.binary_number_op(|a, b| Slots::from_bool((a < b) == false)) { return Err(e); } },
+            OpCode::LessEqual => { if let Err(e) = self.binary_number_op(|a, b| Slots::from_bool((a > b) == false)) { return Err(e); } },
+            OpCode::Add => { if let Err(e) = self.add() { return Err(e); } },
+            OpCode::Subtract => { if let Err(e) = self.binary_number_op(|a, b| Slots::from_number(a - b)) { return Err(e); } },
+            OpCode::Multiply => { if let Err(e) = self.binary_number_op(|a, b| Slots::from_number(a * b)) { return Err(e); } },
+            OpCode::Divide => { if let Err(e) = self.binary_number_op(|a, b| Slots::from_number(a / b)) { return Err(e); } },
             OpCode::Negate => {
                 let val = self.stack.pop().unwrap();
-                if let Value::Number(n) = val {
-                    self.stack.push(Value::Number(-n));
+                if let Some(n) = Slots::number(&val) {
+                    self.stack.push(Slots::from_number(-n));
                 } else { 
                     let err = self.runtime_error("Negate operand must be number."); 
                     return Err(err);
                 }
             },
-            OpCode::Null => self.stack.push(Value::Null),
+            OpCode::Null => self.stack.push(Slots::null()),
             OpCode::DefineGlobal => {
                 self.write_global();
                 self.stack.pop();
@@ -96,38 +171,215 @@ impl VM {
             },
             OpCode::GetGlobal => {
                 match self.read_global() {
-                    Some(global_val) => { self.stack.push(global_val); },
+                    Some(global_val) => { self.push(global_val); },
                     None => {
                         let err = self.runtime_error("Undefined variable.");
                         return Err(err);
                     },
                 }
             },
+            OpCode::GetDefinedGlobal => {
+                let index = self.read_byte() as usize;
+                let val = self.globals[index].clone();
+                self.push(val);
+            },
             OpCode::JumpIfFalse => {
                 let jump = self.read_short() as usize;
-                if self.is_falsey(self.stack.last().unwrap()) {
+                if self.slots.is_falsey(self.stack.last().unwrap()) {
                     self.frame_mut().pc += jump;
                 }
             },
+            OpCode::PopJumpIfFalse => {
+                let jump = self.read_short() as usize;
+                let condition = self.stack.pop().unwrap();
+                if self.slots.is_falsey(&condition) {
+                    self.frame_mut().pc += jump;
+                }
+            },
+            OpCode::IncLocal => {
+                let index = self.read_byte() as usize + self.frame().stack_offset;
+                match Slots::number(&self.stack[index]) {
+                    Some(num) => self.stack[index] = Slots::from_number(num + 1.0),
+                    None => {
+                        let err = self.runtime_error("Add operands must both be strings or numbers");
+                        return Err(err);
+                    },
+                }
+            },
+            OpCode::AddLocalConst => {
+                let local_stack_index = self.read_byte() as usize;
+                let constant = self.read_constant();
+                let index = local_stack_index + self.frame().stack_offset;
+                if let (Some(num), Value::Number(step)) = (Slots::number(&self.stack[index]), &constant) {
+                    self.stack[index] = Slots::from_number(num + step);
+                }
+                else {
+                    // anything but numbers takes the same path as the instructions this replaces
+                    self.stack.push(self.stack[index].clone());
+                    self.push(constant);
+                    if let Err(e) = self.add() { return Err(e); }
+                    let val = self.stack.pop().unwrap();
+                    self.stack_set(local_stack_index, val);
+                }
+            },
+            OpCode::LessLocalConstJump => {
+                let local_stack_index = self.read_byte() as usize;
+                let local = self.stack_get(local_stack_index);
+                let constant = self.read_constant();
+                let jump = self.read_short() as usize;
+                match (Slots::number(&local), constant) {
+                    (Some(num), Value::Number(limit)) => {
+                        if (num < limit) == false {
+                            self.frame_mut().pc += jump;
+                        }
+                    },
+                    _ => {
+                        let err = self.runtime_error("Add operands must both be strings or numbers");
+                        return Err(err);
+                    },
+                }
+            },
             OpCode::Jump => {
                 let jump = self.read_short() as usize;
                 self.frame_mut().pc += jump;
             },
             OpCode::JumpBack => {
+                if self.cancel_token.is_cancelled() { return Err(self.cancelled_error()); }
                 let jump = self.read_short() as usize;
                 self.frame_mut().pc -= jump;
             },
-            OpCode::True => self.stack.push(Value::Bool(true)),
-            OpCode::False => self.stack.push(Value::Bool(false)),
+            OpCode::True => self.stack.push(Slots::from_bool(true)),
+            OpCode::False => self.stack.push(Slots::from_bool(false)),
             OpCode::Call => {
+                if self.cancel_token.is_cancelled() { return Err(self.cancelled_error()); }
                 let arg_count = self.read_byte() as usize;
                 let callee_idx = self.stack.len() - 1 - arg_count;
-                let callee = self.stack[callee_idx].clone();
-                if self.call_value(callee, arg_count) == false {
-                    let err = self.runtime_error("Failed to call callee"); //TODO - trash error
-                    return Err(err);
+                let callee = self.slots.value(&self.stack[callee_idx]);
+                if let Err(e) = self.call_value(callee, arg_count, false, data) { return Err(e); }
+            },
+            OpCode::TailCall => {
+                if self.cancel_token.is_cancelled() { return Err(self.cancelled_error()); }
+                let arg_count = self.read_byte() as usize;
+                let callee_idx = self.stack.len() - 1 - arg_count;
+                let callee = self.slots.value(&self.stack[callee_idx]);
+                if let Err(e) = self.call_value(callee, arg_count, true, data) { return Err(e); }
+            },
+            OpCode::BuildTuple => {
+                let count = self.read_byte() as usize;
+                let values = self.stack.split_off(self.stack.len() - count).into_iter().map(|slot| self.slots.take(slot)).collect();
+                let tuple = match self.allocate(Value::Tuple(Rc::new(values))) {
+                    Ok(tuple) => tuple,
+                    Err(e) => return Err(e),
+                };
+                self.push(tuple);
+            },
+            OpCode::Unpack => {
+                let count = self.read_byte() as usize;
+                match self.pop() {
+                    Value::Tuple(values) => {
+                        if values.len() != count {
+                            let err = self.runtime_error("Tuple size does not match the number of variables.");
+                            return Err(err);
+                        }
+                        for value in values.iter() {
+                            self.push(value.clone());
+                        }
+                    },
+                    _ => {
+                        let err = self.runtime_error("Can only destructure a tuple.");
+                        return Err(err);
+                    }
                 }
             },
+            OpCode::Contains => {
+                let container = self.pop();
+                let item = self.pop();
+                let contains = match (&container, &item) {
+                    (Value::String(string), Value::String(sub_string)) => string.contains(sub_string.as_str()),
+                    (Value::String(_), _) => {
+                        let err = self.runtime_error("Only strings can be found in a string.");
+                        return Err(err);
+                    },
+                    (Value::Tuple(values), _) => values.contains(&item),
+                    (Value::Bool(_), _)
+                    | (Value::Number(_), _)
+                    | (Value::Null, _)
+                    | (Value::Func(_), _)
+                    | (Value::NativeFunc(_), _)
+                    | (Value::Host(_), _) => {
+                        let err = self.runtime_error("Right operand of 'in' must be a string or tuple.");
+                        return Err(err);
+                    },
+                };
+                self.stack.push(Slots::from_bool(contains));
+            },
+            OpCode::Index => {
+                let index = self.pop();
+                let target = self.pop();
+                let index = match index {
+                    Value::Number(index) if index.fract() == 0.0 => index,
+                    _ => {
+                        let err = self.runtime_error("Index must be an integer.");
+                        return Err(err);
+                    }
+                };
+                let len = match &target {
+                    Value::String(string) => string.chars().count(),
+                    Value::Tuple(values) => values.len(),
+                    _ => {
+                        let err = self.runtime_error("Can only index strings and tuples.");
+                        return Err(err);
+                    }
+                };
+                let Some(index) = resolve_index(index, len) else {
+                    let err = self.runtime_error("Index out of range.");
+                    return Err(err);
+                };
+                let val = match target {
+                    Value::String(string) => match self.shared_string(string.chars().nth(index).unwrap().encode_utf8(&mut [0; 4])) {
+                        Ok(val) => val,
+                        Err(e) => return Err(e),
+                    },
+                    Value::Tuple(values) => values[index].clone(),
+                    _ => unreachable!(),
+                };
+                self.push(val);
+            },
+            OpCode::Slice => {
+                let end = self.pop();
+                let start = self.pop();
+                let target = self.pop();
+                let len = match &target {
+                    Value::String(string) => string.chars().count(),
+                    Value::Tuple(values) => values.len(),
+                    _ => {
+                        let err = self.runtime_error("Can only slice strings and tuples.");
+                        return Err(err);
+                    }
+                };
+                let (start, end) = match (start, end) {
+                    (Value::Number(start), Value::Number(end)) => (resolve_slice_bound(start, len), resolve_slice_bound(end, len)),
+                    (Value::Number(start), Value::Null) => (resolve_slice_bound(start, len), Some(len)),
+                    (Value::Null, Value::Number(end)) => (Some(0), resolve_slice_bound(end, len)),
+                    (Value::Null, Value::Null) => (Some(0), Some(len)),
+                    _ => (None, None),
+                };
+                let (Some(start), Some(end)) = (start, end) else {
+                    let err = self.runtime_error("Slice bounds must be integers or null.");
+                    return Err(err);
+                };
+                let end = end.max(start);
+                let val = match target {
+                    Value::String(string) => Value::String(Rc::new(string.chars().skip(start).take(end - start).collect())),
+                    Value::Tuple(values) => Value::Tuple(Rc::new(values[start..end].to_vec())),
+                    _ => unreachable!(),
+                };
+                let val = match self.allocate(val) {
+                    Ok(val) => val,
+                    Err(e) => return Err(e),
+                };
+                self.push(val);
+            },
             OpCode::Return => {
                 let return_val = self.stack.pop().unwrap();
                 self.stack.truncate(self.frame().stack_offset);
@@ -135,6 +387,7 @@ impl VM {
 
                 if self.call_frames.len() == 0 {
                     self.stack.pop();
+                    self.slots.collect(&self.stack);
                     return Ok(false);
                 }
 
@@ -145,9 +398,29 @@ impl VM {
         
     }
 
-    pub fn run(&mut self) -> Result<(), RuntimeError> {
+    /// Steps at most budget times, returning Paused if the script hasn't finished by then.
+    pub fn run_with_budget(&mut self, budget: usize, data: &mut dyn Any) -> Result<RunStatus, RuntimeError> {
+        for _ in 0..budget {
+            match self.step(data) {
+                Ok(has_next) => {
+                    if has_next == false { return Ok(RunStatus::Finished); }
+                },
+                Err(runtime_err) => { return Err(runtime_err); },
+            }
+        }
+        if self.call_frames.len() == 0 { return Ok(RunStatus::Finished); }
+        return Ok(RunStatus::Paused);
+    }
+
+    /// Frees values only kept alive by reference cycles or stale stack slots, returning how many cycle values were freed.
+    pub fn collect_garbage(&mut self) -> usize {
+        self.slots.collect(&self.stack);
+        return self.heap.collect();
+    }
+
+    pub fn run(&mut self, data: &mut dyn Any) -> Result<(), RuntimeError> {
         loop {
-            match self.step() {
+            match self.step(data) {
                 Ok(has_next) => {
                     if has_next == false { return Ok(()); }
                 },
@@ -176,12 +449,27 @@ impl VM {
         return self.call_frames.last_mut().unwrap();
     }
 
-    fn stack_set(&mut self, index: usize, val: Value) {
+    fn push(&mut self, val: Value) {
+        let slot = self.slots.store(val);
+        self.stack.push(slot);
+    }
+
+    fn pop(&mut self) -> Value {
+        let slot = self.stack.pop().unwrap();
+        return self.slots.take(slot);
+    }
+
+    /// Slot distance values down from the top of the stack.
+    fn peek(&self, distance: usize) -> &Slot {
+        return &self.stack[self.stack.len() - 1 - distance];
+    }
+
+    fn stack_set(&mut self, index: usize, val: Slot) {
         let stack_offset = self.frame().stack_offset;
         return self.stack[index + stack_offset] = val;
     }
 
-    fn stack_get(&self, index: usize) -> Value {
+    fn stack_get(&self, index: usize) -> Slot {
         let stack_offset = self.frame().stack_offset;
         return self.stack[index + stack_offset].clone();
     }
@@ -204,60 +492,199 @@ impl VM {
     }
     fn read_global(&mut self) -> Option<Value> {
         let index = self.read_byte() as usize;
-        return self.globals[index].clone();
+        if self.defined[index] == false { return None; }
+        return Some(self.globals[index].clone());
     }
     fn write_global(&mut self) {
-        let val = self.stack.last().unwrap().clone();
+        let val = self.slots.value(self.stack.last().unwrap());
         let index = self.read_byte() as usize;
-        self.globals[index] = Some(val);
+        self.globals[index] = val;
+        self.defined[index] = true;
     }
-    fn runtime_error(&mut self, message: &'static str) -> RuntimeError {
-        println!("Runtime error: {}", message);
-        self.reset_stack();
-        return RuntimeError {
-            message
-        };
+    fn runtime_error(&mut self, message: impl Into<String>) -> RuntimeError {
+        return self.fail(RuntimeError::new(message));
+    }
+    /// Adds the trace to an error if it doesn't have one yet. </br>
+    /// Ends the script unless a native is running, which could still handle the error. The host reports it.
+    fn fail(&mut self, mut err: RuntimeError) -> RuntimeError {
+        if err.trace.is_empty() {
+            err.trace = self.trace();
+        }
+        if self.native_depth == 0 {
+            self.reset_stack();
+        }
+        return err;
+    }
+    /// Where each running function is, innermost first.
+    fn trace(&self) -> Vec<String> {
+        return self.call_frames.iter().rev().map(|frame| {
+            let line = frame.function.chunk.lines.get(frame.pc.saturating_sub(1)).copied().unwrap_or_default();
+            let location = format!("line {} in {}", line, frame.function.name);
+            return match frame.tail_calls {
+                0 => location,
+                tail_calls => format!("{} ({} frames elided by tail calls)", location, tail_calls),
+            };
+        }).collect();
+    }
+    /// Counts a value the vm just created against the memory limit.
+    fn allocate(&mut self, value: Value) -> Result<Value, RuntimeError> {
+        if self.heap.track(&value) == false {
+            // stale stack slots can be holding on to values, let go of them before giving up
+            self.slots.collect(&self.stack);
+            if self.heap.track(&value) { return Ok(value); }
+
+            return Err(self.out_of_memory_error());
+        }
+        return Ok(value);
     }
+    /// Checks bytes more can be allocated without going over the memory limit, without allocating anything.
+    fn reserve(&mut self, bytes: usize) -> Result<(), RuntimeError> {
+        if self.heap.reserve(bytes) { return Ok(()); }
+        self.slots.collect(&self.stack);
+        if self.heap.reserve(bytes) { return Ok(()); }
+        return Err(self.out_of_memory_error());
+    }
+    /// Whether the value is already counted against the memory limit, or is a string literal that never is.
+    fn is_counted(&self, value: &Value) -> bool {
+        if let Value::String(string) = value {
+            if self.interner.get(string).is_some_and(|interned| Rc::ptr_eq(&interned, string)) { return true; }
+        }
+        return self.heap.is_tracked(value);
+    }
+    fn out_of_memory_error(&mut self) -> RuntimeError {
+        let limit = self.heap.limit.unwrap_or_default();
+        let mut err = self.runtime_error(format!("Out of memory, script allocations exceeded the limit of {} bytes.", limit));
+        err.kind = RuntimeErrorKind::OutOfMemory;
+        return err;
+    }
+    /// Gets the compiler's copy of a string if there is one, otherwise allocates a new string. </br>
+    /// Runtime strings are never added to the interner, so they're freed once nothing holds them.
+    fn shared_string(&mut self, string: &str) -> Result<Value, RuntimeError> {
+        if let Some(interned) = self.interner.get(string) {
+            return Ok(Value::String(interned));
+        }
+        return self.allocate(Value::String(Rc::new(string.to_owned())));
+    }
+    fn stack_overflow_error(&mut self, function_name: &str) -> RuntimeError {
+        let mut err = self.runtime_error(format!("Stack overflow in '{}'.", function_name));
+        err.kind = RuntimeErrorKind::StackOverflow;
+        return err;
+    }
+    fn cancelled_error(&mut self) -> RuntimeError {
+        let mut err = self.runtime_error("Script was cancelled.");
+        err.kind = RuntimeErrorKind::Cancelled;
+        return err;
+    }
+    /// Clears the stack and call frames, ending the script.
     fn reset_stack(&mut self) {
         self.stack.clear();
+        self.call_frames.clear();
+        self.slots.collect(&self.stack);
     }
-    fn call_value(&mut self, callee: Value, arg_count: usize) -> bool {
+    /// Calls the callee with the arguments and runs it until it returns. </br>
+    /// On an error the stack and frames are put back how they were, so a native calling back in can carry on if it handles it.
+    pub(crate) fn call_function(&mut self, callee: &Value, args: &[Value], data: &mut dyn Any) -> Result<Value, RuntimeError> {
+        let frame_count = self.call_frames.len();
+        let stack_count = self.stack.len();
+        self.push(callee.clone());
+        for arg in args.iter() {
+            self.push(arg.clone());
+        }
+        let mut result = self.call_value(callee.clone(), args.len(), false, data);
+        while result.is_ok() && self.call_frames.len() > frame_count {
+            result = self.step(data).map(|_| ());
+        }
+        if let Err(e) = result {
+            self.call_frames.truncate(frame_count);
+            self.stack.truncate(stack_count);
+            return Err(e);
+        }
+        return Ok(self.pop());
+    }
+    /// Tail calls to script functions reuse the current frame, moving the callee and arguments down to where it starts. </br>
+    /// Anything else is called the same as a normal call, the Return after it hands back the result.
+    fn call_value(&mut self, callee: Value, arg_count: usize, tail_call: bool, data: &mut dyn Any) -> Result<(), RuntimeError> {
         match callee {
             Value::Bool(_)
             | Value::Number(_)
             | Value::Null
-            | Value::String(_) => {
-                return false;
+            | Value::String(_)
+            | Value::Tuple(_)
+            | Value::Host(_) => {
+                return Err(self.runtime_error("Can only call functions."));
             },
             Value::Func(function) => {
                 if arg_count != function.arity as usize {
-                    //TODO: Allow formatted strings in errors self.runtime_error("Expected {} arguments, but got {}", function.arity, arg_count);
-                    self.runtime_error("Incorrect argument count.");
-                    return false;
+                    let message = format!("Expected {} arguments, but got {}.", function.arity, arg_count);
+                    return Err(self.runtime_error(message));
+                }
+                if tail_call {
+                    let callee_index = self.stack.len() - 1 - arg_count;
+                    let stack_offset = self.frame().stack_offset;
+                    self.stack.drain(stack_offset..callee_index);
+                    let frame = self.frame_mut();
+                    frame.function = function;
+                    frame.pc = 0;
+                    frame.tail_calls += 1;
+                    return Ok(());
+                }
+                if self.call_frames.len() >= self.max_frames || self.stack.len() > self.max_stack {
+                    return Err(self.stack_overflow_error(&function.name));
                 }
                 let frame = CallFrame {
                     function,
                     stack_offset: self.stack.len() - 1 - arg_count,
                     pc: 0,
+                    tail_calls: 0,
                 };
                 self.call_frames.push(frame);
             },
             Value::NativeFunc(native_function) => {
-                let args_slice = &self.stack[(self.stack.len() - arg_count)..self.stack.len()];
-                let return_val = (native_function.function)(args_slice);
-                self.stack.truncate(self.stack.len() - arg_count - 1);
-                self.stack.push(return_val);
+                if arg_count != native_function.arity as usize {
+                    let message = format!("Expected {} arguments, but got {}.", native_function.arity, arg_count);
+                    return Err(self.runtime_error(message));
+                }
+                let callee_index = self.stack.len() - arg_count - 1;
+                // copied out since natives can re-enter the vm and grow the stack
+                let args: Vec<Value> = self.stack[(callee_index + 1)..].iter().map(|slot| self.slots.value(slot)).collect();
+                self.native_depth += 1;
+                let mut ctx = NativeCtx { vm: self, data };
+                let result = (native_function.function)(&mut ctx, &args);
+                self.native_depth -= 1;
+                // passed on as is, errors from calls back into the vm keep their kind and trace
+                let return_val = match result {
+                    Ok(return_val) => return_val,
+                    Err(err) => return Err(self.fail(err)),
+                };
+                // anything the vm isn't already counting is new to the script, so count it like the vm's own
+                let return_val = match self.is_counted(&return_val) {
+                    true => return_val,
+                    false => match self.allocate(return_val) {
+                        Ok(return_val) => return_val,
+                        Err(e) => return Err(e),
+                    },
+                };
+                self.stack.truncate(callee_index);
+                self.push(return_val);
 
             },
         }
 
-        return true;
+        return Ok(());
+    }
+    fn add(&mut self) -> Result<(), RuntimeError> {
+        let numbers = Slots::number(self.peek(0)).is_some() && Slots::number(self.peek(1)).is_some();
+        if numbers == false && matches!(self.slots.value(self.peek(0)), Value::String(_)) && matches!(self.slots.value(self.peek(1)), Value::String(_)) {
+            return self.concatenate();
+        }
+        return self.binary_number_op(|a, b| Slots::from_number(a + b));
     }
-    fn binary_number_op<T>(&mut self, apply: T) -> Result<(), RuntimeError> where T: Fn(f64, f64) -> Value {
+
+    fn binary_number_op<T>(&mut self, apply: T) -> Result<(), RuntimeError> where T: Fn(f64, f64) -> Slot {
         let b = self.stack.pop().unwrap();
         let a = self.stack.pop().unwrap();
-        match (a, b) {
-            (Value::Number(num_a), Value::Number(num_b)) => {
+        match (Slots::number(&a), Slots::number(&b)) {
+            (Some(num_a), Some(num_b)) => {
                 self.stack.push(apply(num_a, num_b));
                 return Ok(());
             },
@@ -269,11 +696,15 @@ impl VM {
     } 
 
     fn concatenate(&mut self) -> Result<(), RuntimeError> {
-        let b = self.stack.pop().unwrap();
-        let a = self.stack.pop().unwrap();
+        let b = self.pop();
+        let a = self.pop();
         match (a, b) {
             (Value::String(str_a), Value::String(str_b)) => {
-                self.stack.push(Value::String(Rc::new(str_a.as_str().to_owned() + str_b.as_str())));
+                let string = match self.allocate(Value::String(Rc::new(str_a.as_str().to_owned() + str_b.as_str()))) {
+                    Ok(string) => string,
+                    Err(e) => return Err(e),
+                };
+                self.push(string);
                 return Ok(());
             },
             _ => { 
@@ -282,8 +713,411 @@ impl VM {
              }
         }    
     }
+}
+
+/// Converts a possibly negative index into a position within len. </br>
+/// Returns None for out of range indices.
+fn resolve_index(index: f64, len: usize) -> Option<usize> {
+    let index = if index < 0.0 { index + len as f64 } else { index };
+    if index < 0.0 || index >= len as f64 { return None; }
+    return Some(index as usize);
+}
+
+/// Converts a possibly negative slice bound into a position clamped to 0..=len. </br>
+/// Returns None for non-integer bounds.
+fn resolve_slice_bound(bound: f64, len: usize) -> Option<usize> {
+    if bound.fract() != 0.0 { return None; }
+    let bound = if bound < 0.0 { bound + len as f64 } else { bound };
+    return Some(bound.clamp(0.0, len as f64) as usize);
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use crate::{cancel::CancelToken, compiler::Compiler, interpreter::{RunStatus, RuntimeErrorKind}, value::Value, vm::VM};
+
+    impl VM {
+        /// The global's value, or None if it hasn't been defined.
+        fn global(&self, index: usize) -> Option<Value> {
+            if self.defined[index] == false { return None; }
+            return Some(self.globals[index].clone());
+        }
+    }
+
+    fn run(source: &str) -> VM {
+        let output = Compiler::new(source).compile().expect("Failed to compile");
+        let mut vm = VM::new(output, CancelToken::new());
+        assert!(vm.run(&mut ()).is_ok(), "Runtime error");
+        return vm;
+    }
+
+    fn run_err(source: &str) -> String {
+        let output = Compiler::new(source).compile().expect("Failed to compile");
+        let mut vm = VM::new(output, CancelToken::new());
+        return vm.run(&mut ()).expect_err("Expected runtime error").message;
+    }
+
+    #[test]
+    fn return_tuple_and_destructure() {
+        let source = r#"
+fn min_max(a, b):
+    if a < b:
+        return a, b
+    return b, a
+
+var low, high = min_max(7, 3)"#;
+        let vm = run(source);
+
+        assert_eq!(Some(Value::Number(3.0)), vm.global(1));
+        assert_eq!(Some(Value::Number(7.0)), vm.global(2));
+    }
+
+    #[test]
+    fn swap_locals() {
+        let source = r#"
+var result
+if true:
+    var a = 1
+    var b = 2
+    a, b = b, a
+    result = (a, b)"#;
+        let vm = run(source);
+
+        let expected = Value::Tuple(Rc::new(vec![Value::Number(2.0), Value::Number(1.0)]));
+        assert_eq!(Some(expected), vm.global(0));
+    }
+
+    #[test]
+    fn fused_comparisons() {
+        let source = r#"
+var one = 1
+var two = 2
+var a = "a"
+var results = (one >= one, one >= two, one <= one, two <= one, one != two, a != a)
+var nan = 0 / 0
+var nan_results = (nan >= 1, nan <= 1, nan != nan)
+var count = 0
+while count <= 3:
+    count = count + 1"#;
+        let vm = run(source);
+
+        let bools = |values: &[bool]| Some(Value::Tuple(Rc::new(values.iter().map(|b| Value::Bool(*b)).collect())));
+        assert_eq!(bools(&[true, false, true, false, true, false]), vm.global(3));
+        assert_eq!(bools(&[true, true, true]), vm.global(5));
+        assert_eq!(Some(Value::Number(4.0)), vm.global(6));
+    }
+
+    #[test]
+    fn local_superinstructions() {
+        let source = r#"
+fn count():
+    var i = 0
+    var total = 0
+    while i < 10:
+        total = total + 2
+        i = i + 1
+    return total
+
+fn greet():
+    var s = "hi"
+    s = s + "!"
+    return s
+
+var total = count()
+var greeting = greet()"#;
+        let output = Compiler::new(source).compile().expect("Failed to compile");
+        let mut vm = VM::new(output, CancelToken::new());
+        let mut steps = 0;
+        while vm.step(&mut ()).expect("Runtime error") {
+            steps += 1;
+        }
+
+        assert_eq!(Some(Value::Number(20.0)), vm.global(2));
+        assert_eq!(Some(Value::String(Rc::new("hi!".to_owned()))), vm.global(3));
+        // the loop runs 4 instructions a lap instead of 15
+        assert_eq!(60, steps);
+
+        let source = r#"
+fn count():
+    var i = "a"
+    while i < 10:
+        i = i + 1
+count()"#;
+        assert_eq!("Add operands must both be strings or numbers", run_err(source));
+    }
+
+    #[test]
+    fn tuple_equality() {
+        let source = r#"
+var same = (1, "a") == (1, "a")
+var different = (1, 2) == (2, 1)"#;
+        let vm = run(source);
+
+        assert_eq!(Some(Value::Bool(true)), vm.global(0));
+        assert_eq!(Some(Value::Bool(false)), vm.global(1));
+    }
+
+    #[test]
+    fn error_destructure_size_mismatch() {
+        let source = r#"
+var t = 1, 2, 3
+var a, b = t"#;
+        assert_eq!("Tuple size does not match the number of variables.", run_err(source));
+    }
 
-    fn is_falsey(&self, val: &Value) -> bool {
-        return *val == Value::Null || *val == Value::Bool(false);
+    #[test]
+    fn error_destructure_non_tuple() {
+        let source = r#"var a, b = 5"#;
+        assert_eq!("Can only destructure a tuple.", run_err(source));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn membership() {
+        let source = r#"
+var in_string = "ell" in "hello"
+var not_in_string = "z" not in "hello"
+var in_tuple = 2 in (1, 2, 3)
+var not_in_tuple = "2" in (1, 2, 3)"#;
+        let vm = run(source);
+
+        assert_eq!(Some(Value::Bool(true)), vm.global(0));
+        assert_eq!(Some(Value::Bool(true)), vm.global(1));
+        assert_eq!(Some(Value::Bool(true)), vm.global(2));
+        assert_eq!(Some(Value::Bool(false)), vm.global(3));
+    }
+
+    #[test]
+    fn error_membership_unsupported_container() {
+        assert_eq!("Right operand of 'in' must be a string or tuple.", run_err("1 in 5"));
+        assert_eq!("Only strings can be found in a string.", run_err("1 in \"1\""));
+    }
+
+    #[test]
+    fn string_index() {
+        let source = r#"
+var name = "héllo"
+var first = name[0]
+var second = name[1]
+var last = name[-1]"#;
+        let vm = run(source);
+
+        assert_eq!(Some(Value::String(Rc::new("h".to_owned()))), vm.global(1));
+        assert_eq!(Some(Value::String(Rc::new("é".to_owned()))), vm.global(2));
+        assert_eq!(Some(Value::String(Rc::new("o".to_owned()))), vm.global(3));
+        // characters taken out at runtime aren't kept alive by the interner
+        assert!(vm.interner.get("é").is_none());
+    }
+
+    #[test]
+    fn string_slice() {
+        let source = r#"
+var name = "héllo"
+var middle = name[1:3]
+var head = name[:2]
+var tail = name[-2:]
+var all = name[:]
+var empty = name[4:1]
+var clamped = name[2:100]"#;
+        let vm = run(source);
+
+        let expected = ["él", "hé", "lo", "héllo", "", "llo"];
+        for (i, expected) in expected.iter().enumerate() {
+            assert_eq!(Some(Value::String(Rc::new(expected.to_string()))), vm.global(i + 1));
+        }
+    }
+
+    #[test]
+    fn tuple_index_and_slice() {
+        let source = r#"
+var t = (1, 2, 3)
+var second = t[1]
+var rest = t[1:]"#;
+        let vm = run(source);
+
+        assert_eq!(Some(Value::Number(2.0)), vm.global(1));
+        assert_eq!(Some(Value::Tuple(Rc::new(vec![Value::Number(2.0), Value::Number(3.0)]))), vm.global(2));
+    }
+
+    #[test]
+    fn error_index() {
+        assert_eq!("Index out of range.", run_err("\"abc\"[3]"));
+        assert_eq!("Index out of range.", run_err("\"abc\"[-4]"));
+        assert_eq!("Index must be an integer.", run_err("\"abc\"[0.5]"));
+        assert_eq!("Index must be an integer.", run_err("\"abc\"[\"0\"]"));
+        assert_eq!("Can only index strings and tuples.", run_err("10[0]"));
+        assert_eq!("Slice bounds must be integers or null.", run_err("\"abc\"[0:1.5]"));
+    }
+
+    #[test]
+    fn error_call_arity() {
+        let source = r#"
+fn add(a, b):
+    return a + b
+add(1)"#;
+        assert_eq!("Expected 2 arguments, but got 1.", run_err(source));
+    }
+
+    #[test]
+    fn run_with_budget() {
+        let source = r#"
+var i = 0
+while i < 100:
+    i = i + 1"#;
+        let output = Compiler::new(source).compile().expect("Failed to compile");
+        let mut vm = VM::new(output, CancelToken::new());
+
+        assert_eq!(Ok(RunStatus::Paused), vm.run_with_budget(50, &mut ()));
+        let Some(Value::Number(partial)) = vm.global(0) else { panic!("Expected i to be defined") };
+        assert!(partial > 0.0 && partial < 100.0);
+
+        let mut pauses = 0;
+        while vm.run_with_budget(50, &mut ()) == Ok(RunStatus::Paused) {
+            pauses += 1;
+        }
+        assert!(pauses > 0);
+        assert_eq!(Some(Value::Number(100.0)), vm.global(0));
+        assert_eq!(Ok(RunStatus::Finished), vm.run_with_budget(50, &mut ()));
+    }
+
+    #[test]
+    fn cancel_infinite_loop() {
+        let source = r#"
+while true:
+    var x = 1"#;
+        let output = Compiler::new(source).compile().expect("Failed to compile");
+        let cancel_token = CancelToken::new();
+        let mut vm = VM::new(output, cancel_token.clone());
+
+        assert_eq!(Ok(RunStatus::Paused), vm.run_with_budget(1000, &mut ()));
+        cancel_token.cancel();
+        let err = vm.run(&mut ()).expect_err("Expected cancellation");
+        assert_eq!(RuntimeErrorKind::Cancelled, err.kind);
+        assert_eq!(Ok(RunStatus::Finished), vm.run_with_budget(1000, &mut ()));
+    }
+
+    #[test]
+    fn cancel_checked_on_calls() {
+        let source = r#"
+fn f():
+    return 1
+f()"#;
+        let output = Compiler::new(source).compile().expect("Failed to compile");
+        let cancel_token = CancelToken::new();
+        cancel_token.cancel();
+        let mut vm = VM::new(output, cancel_token);
+        let err = vm.run(&mut ()).expect_err("Expected cancellation");
+        assert_eq!(RuntimeErrorKind::Cancelled, err.kind);
+
+        let output = Compiler::new("1 + null").compile().expect("Failed to compile");
+        let mut vm = VM::new(output, CancelToken::new());
+        assert_eq!(RuntimeErrorKind::Script, vm.run(&mut ()).expect_err("Expected runtime error").kind);
+    }
+
+    #[test]
+    fn tail_calls() {
+        let source = r#"
+fn countdown(n):
+    if n == 0:
+        return "done"
+    return countdown(n - 1)
+
+fn is_even(n):
+    if n == 0:
+        return true
+    return is_odd(n - 1)
+
+fn is_odd(n):
+    if n == 0:
+        return false
+    return is_even(n - 1)
+
+fn check(flag):
+    return flag and countdown(3)
+
+var done = countdown(100000)
+var even = is_even(10001)
+var skipped = check(false)
+var checked = check(true)"#;
+        let vm = run(source);
+
+        assert_eq!(Some(Value::String(Rc::new("done".to_owned()))), vm.global(4));
+        assert_eq!(Some(Value::Bool(false)), vm.global(5));
+        assert_eq!(Some(Value::Bool(false)), vm.global(6));
+        assert_eq!(Some(Value::String(Rc::new("done".to_owned()))), vm.global(7));
+    }
+
+    #[test]
+    fn tail_call_after_dead_branch() {
+        // the call in the dead branch is thrown away, the return isn't a call even though it ends where it was
+        let source = r#"
+fn f():
+    return 1
+
+fn g(a, b):
+    if false:
+        f()
+    return a = b
+
+var result = g(1, 2)"#;
+        let vm = run(source);
+        assert_eq!(Some(Value::Number(2.0)), vm.global(2));
+    }
+
+    #[test]
+    fn tail_call_trace() {
+        let source = r#"
+fn fail(n):
+    if n == 0:
+        return 1 + null
+    return fail(n - 1)
+
+fn start():
+    var result = fail(3)
+    return result
+
+start()"#;
+        let output = Compiler::new(source).compile().expect("Failed to compile");
+        let mut vm = VM::new(output, CancelToken::new());
+        let err = vm.run(&mut ()).expect_err("Expected runtime error");
+        let expected = vec![
+            "line 4 in fail (3 frames elided by tail calls)".to_owned(),
+            "line 8 in start".to_owned(),
+            "line 11 in script".to_owned(),
+        ];
+        assert_eq!(expected, err.trace);
+    }
+
+    #[test]
+    fn late_bound_global() {
+        let source = r#"
+fn early():
+    return late()
+early()
+fn late():
+    return 1"#;
+        assert_eq!("Undefined variable.", run_err(source));
+
+        let source = r#"
+fn early():
+    return late()
+fn late():
+    return 1
+var one = early()"#;
+        assert_eq!(Some(Value::Number(1.0)), run(source).global(2));
+    }
+
+    #[test]
+    fn stack_overflow() {
+        let source = r#"
+fn forever(n):
+    return 1 + forever(n + 1)
+forever(0)"#;
+        let output = Compiler::new(source).compile().expect("Failed to compile");
+        let mut vm = VM::new(output, CancelToken::new());
+        let err = vm.run(&mut ()).expect_err("Expected stack overflow");
+        assert_eq!(RuntimeErrorKind::StackOverflow, err.kind);
+        assert_eq!("Stack overflow in 'forever'.", err.message);
+        assert_eq!(0, vm.stack.len());
+    }
+}